@@ -0,0 +1,270 @@
+use crate::error::Error;
+use crate::http::{
+    rejection_status, MAuthHeaders, RequestAuthenticator, DEFAULT_BODY_LIMIT, MAUTH_HEADERS,
+};
+use crate::policy::{AuthPolicy, Enforcement};
+use actix_web::body::EitherBody;
+use actix_web::dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::StatusCode;
+use actix_web::{HttpMessage, HttpResponse};
+use bytes::{Bytes, BytesMut};
+use futures_util::future::LocalBoxFuture;
+use futures_util::StreamExt;
+use std::future::{ready, Ready};
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// actix-web middleware that authenticates MAuth signed requests, for services that are not built
+/// on tower.
+///
+/// The request payload is collected, up to a limit, verified on actix's blocking thread pool and
+/// then put back for the handlers to read. Requests that pass have an `http::AuthenticatedApp`
+/// inserted into their extensions, which handlers can extract with
+/// `web::ReqData<AuthenticatedApp>`. Routes are handled according to a `policy::AuthPolicy` in the
+/// same way as `tower_server::AuthenticationLayer`, and rejected requests get an empty response
+/// with the status from `http::rejection_status`.
+///
+/// ```
+/// # use actix_web::{web, App};
+/// # use mauth_core::actix::MAuthMiddleware;
+/// # use mauth_core::authenticator::Authenticator;
+/// # use mauth_core::http::{AuthenticatedApp, RequestAuthenticator};
+/// # use mauth_core::keys::{KeyPair, KeySize, PublicKeyFormat};
+/// # use mauth_core::verifier::Verifier;
+/// # let public_key = KeyPair::generate(KeySize::Rsa2048).unwrap().public_key_pem(PublicKeyFormat::Spki).unwrap();
+/// async fn handler(app: web::ReqData<AuthenticatedApp>) -> String {
+///     format!("Hello {}", app.app_uuid)
+/// }
+///
+/// let verifier = Verifier::new("101c139a-236c-11ef-b5e3-125eb8485a60", public_key).unwrap();
+/// let authenticator = RequestAuthenticator::new(Authenticator::from(verifier));
+/// let app = App::new()
+///     .wrap(MAuthMiddleware::new(authenticator))
+///     .route("/", web::get().to(handler));
+/// ```
+#[derive(Debug, Clone)]
+pub struct MAuthMiddleware {
+    authenticator: Arc<RequestAuthenticator>,
+    policy: Arc<AuthPolicy>,
+    body_limit: usize,
+}
+
+impl MAuthMiddleware {
+    /// Initialize middleware that authenticates requests with `authenticator`, collecting payloads
+    /// of up to `http::DEFAULT_BODY_LIMIT` bytes.
+    pub fn new(authenticator: RequestAuthenticator) -> Self {
+        Self {
+            authenticator: Arc::new(authenticator),
+            policy: Arc::default(),
+            body_limit: DEFAULT_BODY_LIMIT,
+        }
+    }
+
+    /// Set the largest payload, in bytes, that will be collected for verification
+    pub fn with_body_limit(mut self, body_limit: usize) -> Self {
+        self.body_limit = body_limit;
+        self
+    }
+
+    /// Set the policy deciding how failures are handled on each route
+    pub fn with_policy(mut self, policy: AuthPolicy) -> Self {
+        self.policy = Arc::new(policy);
+        self
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for MAuthMiddleware
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Transform = MAuthService<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(MAuthService {
+            service: Rc::new(service),
+            middleware: self.clone(),
+        }))
+    }
+}
+
+/// The service created by `MAuthMiddleware`
+#[derive(Debug)]
+pub struct MAuthService<S> {
+    service: Rc<S>,
+    middleware: MAuthMiddleware,
+}
+
+impl<S, B> Service<ServiceRequest> for MAuthService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, mut request: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let MAuthMiddleware {
+            authenticator,
+            policy,
+            body_limit,
+        } = self.middleware.clone();
+
+        Box::pin(async move {
+            let method = request.method().as_str().to_owned();
+            let path = request.path().to_owned();
+            if policy.enforcement(&method, &path) == Enforcement::Exempt {
+                return Ok(service.call(request).await?.map_into_left_body());
+            }
+
+            let body = match read_payload(request.take_payload(), body_limit).await {
+                Ok(body) => body,
+                Err(e) => return Ok(reject(request, &e)),
+            };
+            request.set_payload(Payload::from(body.clone()));
+
+            let query = request.query_string().to_owned();
+            // The headers are copied out to authenticate the request on the blocking thread pool
+            let headers: Vec<(&str, String)> = MAUTH_HEADERS
+                .into_iter()
+                .filter_map(|name| {
+                    let value = request.headers().get(name)?.to_str().ok()?;
+                    Some((name, value.to_owned()))
+                })
+                .collect();
+            let verified = actix_web::web::block({
+                let (method, path) = (method.clone(), path.clone());
+                let headers = headers.clone();
+                move || authenticator.authenticate(&method, &path, &query, &body, header(&headers))
+            })
+            .await;
+            let Ok(result) = verified else {
+                let response = HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR);
+                return Ok(request.into_response(response).map_into_right_body());
+            };
+
+            let app_uuid = result
+                .is_err()
+                .then(|| MAuthHeaders::parse(header(&headers), false).ok())
+                .flatten()
+                .map(|headers| headers.app_uuid);
+            match policy.apply(&method, &path, app_uuid.as_deref(), result) {
+                Ok(Some(app)) => {
+                    request.extensions_mut().insert(app);
+                }
+                Ok(None) => {}
+                Err(e) => return Ok(reject(request, &e)),
+            }
+
+            Ok(service.call(request).await?.map_into_left_body())
+        })
+    }
+}
+
+fn header<'a>(headers: &'a [(&str, String)]) -> impl Fn(&str) -> Option<&'a str> {
+    move |name| {
+        headers
+            .iter()
+            .find(|(header, _)| *header == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+async fn read_payload(mut payload: Payload, limit: usize) -> Result<Bytes, Error> {
+    let mut body = BytesMut::new();
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk.map_err(|e| Error::BodyReadError(Box::new(e)))?;
+        if body.len() + chunk.len() > limit {
+            return Err(Error::BodyTooLarge(limit));
+        }
+        body.extend_from_slice(&chunk);
+    }
+
+    Ok(body.freeze())
+}
+
+fn reject<B>(request: ServiceRequest, error: &Error) -> ServiceResponse<EitherBody<B>> {
+    let status =
+        StatusCode::from_u16(rejection_status(error).as_u16()).unwrap_or(StatusCode::UNAUTHORIZED);
+    request
+        .into_response(HttpResponse::new(status))
+        .map_into_right_body()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::tests::{authenticator, now};
+    use crate::http::{AuthenticatedApp, X_MWS_AUTHENTICATION, X_MWS_TIME};
+    use crate::keys::tests::{signer, APP_UUID};
+    use actix_web::{test, web, App};
+
+    async fn echo(app: Option<web::ReqData<AuthenticatedApp>>, body: Bytes) -> String {
+        let app_uuid = app.map_or("none".to_owned(), |app| app.app_uuid.clone());
+        format!("{app_uuid} {}", String::from_utf8_lossy(&body))
+    }
+
+    fn signed_request(body: &'static [u8]) -> test::TestRequest {
+        let time = now();
+        let signature = signer()
+            .sign_string(1, "POST", "/items", "", body, &time)
+            .unwrap();
+        test::TestRequest::post()
+            .uri("/items")
+            .insert_header((X_MWS_AUTHENTICATION, format!("MWS {APP_UUID}:{signature}")))
+            .insert_header((X_MWS_TIME, time))
+            .set_payload(body)
+    }
+
+    fn middleware() -> MAuthMiddleware {
+        let mut policy = AuthPolicy::default();
+        policy.route("GET /health", Enforcement::Exempt).unwrap();
+        MAuthMiddleware::new(RequestAuthenticator::new(authenticator()))
+            .with_body_limit(16)
+            .with_policy(policy)
+    }
+
+    #[actix_web::test]
+    async fn signed_requests_reach_the_handler_with_their_body() {
+        let app = test::init_service(
+            App::new()
+                .wrap(middleware())
+                .route("/items", web::post().to(echo)),
+        )
+        .await;
+
+        let response = test::call_service(&app, signed_request(b"{}").to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(test::read_body(response).await, format!("{APP_UUID} {{}}"));
+    }
+
+    #[actix_web::test]
+    async fn requests_are_rejected_unless_exempt() {
+        let app = test::init_service(
+            App::new()
+                .wrap(middleware())
+                .route("/items", web::post().to(echo))
+                .route("/health", web::get().to(echo)),
+        )
+        .await;
+
+        let tampered = signed_request(b"{}").set_payload("[]").to_request();
+        let oversized = signed_request(b"0123456789abcdefg").to_request();
+        let health = test::TestRequest::get().uri("/health").to_request();
+
+        let response = test::call_service(&app, tampered).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = test::call_service(&app, oversized).await;
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        let response = test::call_service(&app, health).await;
+        assert_eq!(test::read_body(response).await, "none ");
+    }
+}
@@ -0,0 +1,358 @@
+use crate::revocation::RevocationList;
+use crate::verifier::{RotatingVerifier, VerificationKey, Verifier};
+use crate::{error::Error, keys::KeyFingerprint};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// A source of the public keys used to verify requests from other apps.
+pub trait KeyProvider: Send + Sync {
+    /// The verifier holding the keys of an app, or `None` if the app is not known to the provider.
+    /// An error should only be returned if the provider was unable to look the app up.
+    fn verifier(&self, app_uuid: &str) -> Result<Option<Arc<RotatingVerifier>>, Error>;
+}
+
+impl<P: KeyProvider + ?Sized> KeyProvider for Arc<P> {
+    fn verifier(&self, app_uuid: &str) -> Result<Option<Arc<RotatingVerifier>>, Error> {
+        (**self).verifier(app_uuid)
+    }
+}
+
+impl<P: KeyProvider + ?Sized> KeyProvider for Box<P> {
+    fn verifier(&self, app_uuid: &str) -> Result<Option<Arc<RotatingVerifier>>, Error> {
+        (**self).verifier(app_uuid)
+    }
+}
+
+/// A fixed set of verifiers, keyed by app UUID
+impl KeyProvider for HashMap<String, Arc<RotatingVerifier>> {
+    fn verifier(&self, app_uuid: &str) -> Result<Option<Arc<RotatingVerifier>>, Error> {
+        Ok(self.get(app_uuid).cloned())
+    }
+}
+
+/// Looks app keys up in several providers in turn, for example a local trust store, then a disk
+/// cache, then the MAuth service. The first provider that knows the app wins. A provider that fails
+/// is skipped, and its error is only returned if no later provider knows the app either.
+///
+/// ```
+/// # use mauth_core::authenticator::{ChainKeyProvider, KeyProvider};
+/// # use mauth_core::verifier::RotatingVerifier;
+/// # use std::collections::HashMap;
+/// # use std::sync::Arc;
+/// # let verifier = Arc::new(RotatingVerifier::new("101c139a-236c-11ef-b5e3-125eb8485a60"));
+/// let local: HashMap<String, Arc<RotatingVerifier>> = HashMap::new();
+/// let remote = HashMap::from([("101c139a-236c-11ef-b5e3-125eb8485a60".to_string(), verifier)]);
+/// let chain = ChainKeyProvider::new().with(local).with(remote);
+///
+/// assert!(chain.verifier("101c139a-236c-11ef-b5e3-125eb8485a60").unwrap().is_some());
+/// ```
+#[derive(Default)]
+pub struct ChainKeyProvider {
+    providers: Vec<Box<dyn KeyProvider>>,
+}
+
+impl std::fmt::Debug for ChainKeyProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ChainKeyProvider")
+            .field("providers", &self.providers.len())
+            .finish()
+    }
+}
+
+impl ChainKeyProvider {
+    /// Initialize an empty chain
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a provider to the end of the chain
+    pub fn with(mut self, provider: impl KeyProvider + 'static) -> Self {
+        self.providers.push(Box::new(provider));
+        self
+    }
+}
+
+impl KeyProvider for ChainKeyProvider {
+    fn verifier(&self, app_uuid: &str) -> Result<Option<Arc<RotatingVerifier>>, Error> {
+        let mut first_error = None;
+        for provider in &self.providers {
+            match provider.verifier(app_uuid) {
+                Ok(Some(verifier)) => return Ok(Some(verifier)),
+                Ok(None) => {}
+                Err(e) => {
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+
+        match first_error {
+            Some(e) => Err(e),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Used to verify incoming requests from any app whose keys are known to a `KeyProvider`. Struct
+/// can be initialized once and used to verify many requests, and clones share the same provider.
+#[derive(Clone)]
+pub struct Authenticator {
+    provider: Arc<dyn KeyProvider>,
+    revocations: Option<Arc<RevocationList>>,
+}
+
+impl std::fmt::Debug for Authenticator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Authenticator").finish_non_exhaustive()
+    }
+}
+
+impl Authenticator {
+    /// Initialize a new authenticator that looks app keys up in the provider
+    pub fn new(provider: impl KeyProvider + 'static) -> Self {
+        Self {
+            provider: Arc::new(provider),
+            revocations: None,
+        }
+    }
+
+    /// Consult a revocation list after each signature is verified, returning `Error::KeyRevoked`
+    /// if the app or the key that matched has been revoked. This applies whichever provider the
+    /// key came from, including caches.
+    pub fn with_revocation_list(mut self, revocations: Arc<RevocationList>) -> Self {
+        self.revocations = Some(revocations);
+        self
+    }
+
+    /// The key provider used to look app keys up
+    pub fn provider(&self) -> &dyn KeyProvider {
+        &*self.provider
+    }
+
+    /// Verify a signature claimed to come from `app_uuid`, in the same way as
+    /// `RotatingVerifier::verify_signature`. On success, the fingerprint of the key that matched is
+    /// returned. `Error::UnknownApp` is returned if the provider does not know the app.
+    ///
+    /// ```
+    /// # use mauth_core::authenticator::Authenticator;
+    /// # use mauth_core::keys::{KeyPair, KeySize, PrivateKeyFormat};
+    /// # use mauth_core::signer::Signer;
+    /// # use mauth_core::verifier::{RotatingVerifier, VerificationKey};
+    /// # use std::collections::HashMap;
+    /// # use std::sync::Arc;
+    /// # let app_uuid = "101c139a-236c-11ef-b5e3-125eb8485a60";
+    /// # let signer = Signer::new(app_uuid, KeyPair::generate(KeySize::Rsa2048).unwrap().private_key_pem(PrivateKeyFormat::Pkcs1).unwrap().to_string()).unwrap();
+    /// let mut verifier = RotatingVerifier::new(app_uuid);
+    /// verifier.add_key(VerificationKey::new(signer.verifier())).unwrap();
+    /// let authenticator = Authenticator::new(HashMap::from([(app_uuid.to_string(), Arc::new(verifier))]));
+    ///
+    /// let signature = signer.sign_string(2, "GET", "/item", "page=2", b"", "1669858655").unwrap();
+    /// let result = authenticator.verify_signature(app_uuid, 2, "GET", "/item", "page=2", b"", "1669858655", signature);
+    /// assert_eq!(result.unwrap(), signer.fingerprint());
+    /// ```
+    #[allow(clippy::too_many_arguments)]
+    pub fn verify_signature(
+        &self,
+        app_uuid: &str,
+        version: u8,
+        verb: impl Into<String>,
+        path: impl Into<String>,
+        query: impl Into<String>,
+        body: &[u8],
+        timestamp: impl Into<String>,
+        signature: impl Into<String>,
+    ) -> Result<KeyFingerprint, Error> {
+        self.verify_with(app_uuid, |verifier| {
+            verifier.verify_signature(version, verb, path, query, body, timestamp, signature)
+        })
+    }
+
+    /// Verify the signature of a response claimed to come from `app_uuid`, in the same way as
+    /// `RotatingVerifier::verify_response_signature`. `Error::UnknownApp` is returned if the
+    /// provider does not know the app.
+    pub fn verify_response_signature(
+        &self,
+        app_uuid: &str,
+        version: u8,
+        status: u16,
+        body: &[u8],
+        timestamp: impl Into<String>,
+        signature: impl Into<String>,
+    ) -> Result<KeyFingerprint, Error> {
+        self.verify_with(app_uuid, |verifier| {
+            verifier.verify_response_signature(version, status, body, timestamp, signature)
+        })
+    }
+
+    /// Verify the signature of a message claimed to come from `app_uuid`, in the same way as
+    /// `RotatingVerifier::verify_message_signature`. `Error::UnknownApp` is returned if the
+    /// provider does not know the app.
+    pub fn verify_message_signature(
+        &self,
+        app_uuid: &str,
+        topic: impl Into<String>,
+        payload: &[u8],
+        timestamp: impl Into<String>,
+        signature: impl Into<String>,
+    ) -> Result<KeyFingerprint, Error> {
+        self.verify_with(app_uuid, |verifier| {
+            verifier.verify_message_signature(topic, payload, timestamp, signature)
+        })
+    }
+
+    pub(crate) fn verify_with(
+        &self,
+        app_uuid: &str,
+        verify: impl FnOnce(&RotatingVerifier) -> Result<KeyFingerprint, Error>,
+    ) -> Result<KeyFingerprint, Error> {
+        let verifier = self
+            .provider
+            .verifier(app_uuid)?
+            .ok_or_else(|| Error::UnknownApp(app_uuid.to_owned()))?;
+
+        let fingerprint = verify(&verifier)?;
+        if let Some(revocations) = &self.revocations {
+            revocations.check(app_uuid, &fingerprint, SystemTime::now())?;
+        }
+
+        Ok(fingerprint)
+    }
+}
+
+impl From<Verifier> for Authenticator {
+    /// An authenticator that only knows the app of a single verifier
+    fn from(verifier: Verifier) -> Self {
+        let app_uuid = verifier.app_uuid().to_owned();
+        let mut rotating = RotatingVerifier::new(&app_uuid);
+        rotating
+            .add_key(VerificationKey::new(verifier))
+            .expect("a verifier's key belongs to its own app");
+
+        Self::new(HashMap::from([(app_uuid, Arc::new(rotating))]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::tests::{signer, APP_UUID};
+
+    fn authenticator() -> Authenticator {
+        let mut verifier = RotatingVerifier::new(APP_UUID);
+        verifier
+            .add_key(VerificationKey::new(signer().verifier()))
+            .unwrap();
+        Authenticator::new(HashMap::from([(APP_UUID.to_owned(), Arc::new(verifier))]))
+    }
+
+    #[test]
+    fn verify_signature_uses_the_apps_keys() {
+        let signer = signer();
+        let signature = signer
+            .sign_string(1, "POST", "/", "", b"{}", "1669858655")
+            .unwrap();
+
+        let result = authenticator().verify_signature(
+            APP_UUID,
+            1,
+            "POST",
+            "/",
+            "",
+            b"{}",
+            "1669858655",
+            signature,
+        );
+        assert_eq!(result.unwrap(), signer.fingerprint());
+    }
+
+    #[test]
+    fn from_verifier_knows_only_its_app() {
+        let authenticator = Authenticator::from(signer().verifier());
+
+        assert!(authenticator
+            .provider()
+            .verifier(APP_UUID)
+            .unwrap()
+            .is_some());
+        assert!(authenticator
+            .provider()
+            .verifier("other")
+            .unwrap()
+            .is_none());
+    }
+
+    struct FailingProvider;
+
+    impl KeyProvider for FailingProvider {
+        fn verifier(&self, _: &str) -> Result<Option<Arc<RotatingVerifier>>, Error> {
+            Err(Error::IoError(std::io::ErrorKind::TimedOut.into()))
+        }
+    }
+
+    #[test]
+    fn chain_falls_back_past_missing_apps_and_failures() {
+        let verifier = Arc::new(RotatingVerifier::new(APP_UUID));
+        let chain = ChainKeyProvider::new()
+            .with(HashMap::new())
+            .with(FailingProvider)
+            .with(HashMap::from([(APP_UUID.to_owned(), verifier.clone())]));
+
+        assert!(Arc::ptr_eq(
+            &chain.verifier(APP_UUID).unwrap().unwrap(),
+            &verifier
+        ));
+        assert!(matches!(chain.verifier("unknown"), Err(Error::IoError(_))));
+        assert!(ChainKeyProvider::new()
+            .with(HashMap::new())
+            .verifier("unknown")
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn verify_signature_rejects_revoked_apps() {
+        let signer = signer();
+        let signature = signer
+            .sign_string(2, "GET", "/", "", b"", "1669858655")
+            .unwrap();
+        let revocations = Arc::new(RevocationList::new());
+        let authenticator = authenticator().with_revocation_list(revocations.clone());
+        revocations.revoke(crate::revocation::Revocation::app(APP_UUID));
+
+        let result = authenticator.verify_signature(
+            APP_UUID,
+            2,
+            "GET",
+            "/",
+            "",
+            b"",
+            "1669858655",
+            signature,
+        );
+        assert!(matches!(result, Err(Error::KeyRevoked { .. })));
+    }
+
+    #[test]
+    fn verify_signature_rejects_unknown_apps() {
+        let result =
+            authenticator().verify_signature("unknown", 2, "GET", "/", "", b"", "1669858655", "");
+
+        assert!(matches!(result, Err(Error::UnknownApp(app_uuid)) if app_uuid == "unknown"));
+    }
+
+    #[test]
+    fn verify_response_signature_uses_the_apps_keys() {
+        let signer = signer();
+        let signature = signer.sign_response(2, 204, b"", "1669858655").unwrap();
+
+        let result = authenticator().verify_response_signature(
+            APP_UUID,
+            2,
+            204,
+            b"",
+            "1669858655",
+            signature,
+        );
+        assert_eq!(result.unwrap(), signer.fingerprint());
+    }
+}
@@ -0,0 +1,222 @@
+use crate::error::Error;
+use crate::http::{rejection_status, AuthenticatedApp, RequestAuthenticator, DEFAULT_BODY_LIMIT};
+use crate::tower_server::{authenticate_blocking, read_body};
+use axum::extract::{FromRef, FromRequest, FromRequestParts, OriginalUri, Request};
+use axum::response::{IntoResponse, Response};
+use bytes::Bytes;
+use http::request::Parts;
+use http::StatusCode;
+use std::sync::Arc;
+
+/// Extracts the app that a request was authenticated as by `tower_server::AuthenticationLayer`,
+/// rejecting the request with `401 Unauthorized` if it was not authenticated. This includes
+/// requests that failed authentication on a route in shadow mode.
+///
+/// ```
+/// # use axum::{routing::get, Router};
+/// # use mauth_core::authenticator::Authenticator;
+/// # use mauth_core::axum_extract::MAuthPrincipal;
+/// # use mauth_core::http::RequestAuthenticator;
+/// # use mauth_core::tower_server::AuthenticationLayer;
+/// # use mauth_core::verifier::RotatingVerifier;
+/// # use std::collections::HashMap;
+/// # use std::sync::Arc;
+/// # let verifiers: HashMap<String, Arc<RotatingVerifier>> = HashMap::new();
+/// async fn handler(MAuthPrincipal(app): MAuthPrincipal) -> String {
+///     format!("Hello {}", app.app_uuid)
+/// }
+///
+/// let authenticator = RequestAuthenticator::new(Authenticator::new(verifiers));
+/// let app: Router = Router::new()
+///     .route("/", get(handler))
+///     .layer(AuthenticationLayer::new(authenticator));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MAuthPrincipal(pub AuthenticatedApp);
+
+impl<S: Send + Sync> FromRequestParts<S> for MAuthPrincipal {
+    type Rejection = MAuthRejection;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts
+            .extensions
+            .get::<AuthenticatedApp>()
+            .cloned()
+            .map(MAuthPrincipal)
+            .ok_or(MAuthRejection(Error::MissingAuthentication))
+    }
+}
+
+/// Extracts the body of a request along with the app that signed it, authenticating the request
+/// with the `RequestAuthenticator` in the router state if `tower_server::AuthenticationLayer` has
+/// not already done so. This is for routers that only authenticate some of their routes, without
+/// the layer. Like any extractor that reads the body, it must be the last argument of a handler.
+///
+/// Requests are authenticated with the URI they were sent to, including the prefix of any
+/// `Router::nest` that the handler is under. Bodies over `http::DEFAULT_BODY_LIMIT` are rejected
+/// with `413 Payload Too Large`, and requests that fail authentication with `401 Unauthorized`.
+///
+/// ```
+/// # use axum::{routing::post, Router};
+/// # use mauth_core::authenticator::Authenticator;
+/// # use mauth_core::axum_extract::MAuthRequest;
+/// # use mauth_core::http::RequestAuthenticator;
+/// # use mauth_core::verifier::RotatingVerifier;
+/// # use std::collections::HashMap;
+/// # use std::sync::Arc;
+/// # let verifiers: HashMap<String, Arc<RotatingVerifier>> = HashMap::new();
+/// async fn handler(MAuthRequest { app, body }: MAuthRequest) -> String {
+///     format!("{} sent {} bytes", app.app_uuid, body.len())
+/// }
+///
+/// let authenticator = Arc::new(RequestAuthenticator::new(Authenticator::new(verifiers)));
+/// let app: Router = Router::new()
+///     .route("/", post(handler))
+///     .with_state(authenticator);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MAuthRequest {
+    /// The app that signed the request
+    pub app: AuthenticatedApp,
+    /// The request body
+    pub body: Bytes,
+}
+
+impl<S> FromRequest<S> for MAuthRequest
+where
+    S: Send + Sync,
+    Arc<RequestAuthenticator>: FromRef<S>,
+{
+    type Rejection = MAuthRejection;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let (mut parts, body) = request.into_parts();
+        let body = read_body(body, DEFAULT_BODY_LIMIT)
+            .await
+            .map_err(MAuthRejection)?;
+        if let Some(app) = parts.extensions.get::<AuthenticatedApp>() {
+            let app = app.clone();
+            return Ok(Self { app, body });
+        }
+        // Nested routers strip their prefix from the URI, which the client signed
+        if let Some(OriginalUri(uri)) = parts.extensions.get::<OriginalUri>() {
+            parts.uri = uri.clone();
+        }
+
+        let authenticator = Arc::<RequestAuthenticator>::from_ref(state);
+        match authenticate_blocking(authenticator, parts, body).await {
+            Ok((_, body, Ok(app))) => Ok(Self { app, body }),
+            Ok((_, _, Err(e))) => Err(MAuthRejection(e)),
+            Err(e) => Err(MAuthRejection(Error::IoError(std::io::Error::other(e)))),
+        }
+    }
+}
+
+/// The rejection returned by the MAuth extractors, holding the reason the request was rejected
+#[derive(Debug)]
+pub struct MAuthRejection(pub Error);
+
+impl MAuthRejection {
+    /// The HTTP status the request is rejected with
+    pub fn status(&self) -> StatusCode {
+        rejection_status(&self.0)
+    }
+}
+
+impl IntoResponse for MAuthRejection {
+    fn into_response(self) -> Response {
+        self.status().into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::tests::{authenticator, now};
+    use crate::http::{MCC_AUTHENTICATION, MCC_TIME};
+    use crate::keys::tests::{signer, APP_UUID};
+    use crate::tower_server::tests::signed_request;
+    use crate::tower_server::AuthenticationLayer;
+    use axum::body::Body;
+    use axum::routing::put;
+    use axum::Router;
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+
+    async fn principal(MAuthPrincipal(app): MAuthPrincipal) -> String {
+        app.app_uuid
+    }
+
+    async fn echo(MAuthRequest { app, body }: MAuthRequest) -> String {
+        format!("{} {}", app.app_uuid, String::from_utf8_lossy(&body))
+    }
+
+    async fn call(router: Router, request: Request) -> (StatusCode, String) {
+        let response = router.oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    fn axum_request(request: http::Request<http_body_util::Full<Bytes>>) -> Request {
+        request.map(Body::new)
+    }
+
+    #[tokio::test]
+    async fn principal_reads_the_app_authenticated_by_the_layer() {
+        let router = Router::new().route("/items/1", put(principal));
+        let layered = router
+            .clone()
+            .layer(AuthenticationLayer::new(RequestAuthenticator::new(
+                authenticator(),
+            )));
+
+        let (status, body) = call(layered, axum_request(signed_request(b"{}"))).await;
+        assert_eq!((status, body.as_str()), (StatusCode::OK, APP_UUID));
+
+        let (status, _) = call(router, axum_request(signed_request(b"{}"))).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn request_authenticates_with_the_state() {
+        let router = Router::new()
+            .route("/items/1", put(echo).get(echo))
+            .with_state(Arc::new(RequestAuthenticator::new(authenticator())));
+
+        let (status, body) = call(router.clone(), axum_request(signed_request(b"{}"))).await;
+        assert_eq!((status, body), (StatusCode::OK, format!("{APP_UUID} {{}}")));
+
+        let unsigned = Request::get("/items/1").body(Body::empty()).unwrap();
+        let (status, _) = call(router, unsigned).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn request_authenticates_nested_routes_with_the_original_uri() {
+        let router = Router::new()
+            .nest("/api", Router::new().route("/items/1", put(echo)))
+            .with_state(Arc::new(RequestAuthenticator::new(authenticator())));
+        let time = now();
+        let signature = signer()
+            .sign_string(2, "PUT", "/api/items/1", "page=2", b"{}", &time)
+            .unwrap();
+        let request = Request::put("/api/items/1?page=2")
+            .header(MCC_AUTHENTICATION, format!("MWSV2 {APP_UUID}:{signature};"))
+            .header(MCC_TIME, time)
+            .body(Body::from("{}"))
+            .unwrap();
+
+        let (status, body) = call(router, request).await;
+
+        assert_eq!((status, body), (StatusCode::OK, format!("{APP_UUID} {{}}")));
+    }
+
+    #[test]
+    fn rejections_use_the_status_of_the_error() {
+        assert_eq!(
+            MAuthRejection(Error::AppNotAllowed(APP_UUID.to_owned())).status(),
+            StatusCode::FORBIDDEN
+        );
+    }
+}
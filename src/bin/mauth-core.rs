@@ -0,0 +1,112 @@
+use clap::{Parser, Subcommand};
+use mauth_core::authenticator::Authenticator;
+use mauth_core::detached::{DetachedSignature, FILE_EXTENSION};
+use mauth_core::error::Error;
+use mauth_core::signer::Signer;
+use mauth_core::verifier::Verifier;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::{SystemTime, UNIX_EPOCH};
+use zeroize::Zeroizing;
+
+/// Create and verify MAuth detached signatures for files and artifacts
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Sign a file with an app's private key, writing its detached signature next to it
+    Sign {
+        /// The UUID of the app signing the file
+        #[arg(long)]
+        app_uuid: String,
+        /// The PEM file holding the app's RSA private key
+        #[arg(long)]
+        private_key: PathBuf,
+        /// Where to write the signature, instead of the file path with `.mauthsig` appended
+        #[arg(long)]
+        output: Option<PathBuf>,
+        /// The file to sign
+        file: PathBuf,
+    },
+    /// Verify that a file was signed by an app with the given public key
+    Verify {
+        /// The UUID of the app the file is expected to come from
+        #[arg(long)]
+        app_uuid: String,
+        /// The PEM file holding the app's RSA public key
+        #[arg(long)]
+        public_key: PathBuf,
+        /// The signature to verify, instead of the file path with `.mauthsig` appended
+        #[arg(long)]
+        signature: Option<PathBuf>,
+        /// The file to verify
+        file: PathBuf,
+    },
+}
+
+fn main() -> ExitCode {
+    match run(Cli::parse().command) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(command: Command) -> Result<(), Error> {
+    match command {
+        Command::Sign {
+            app_uuid,
+            private_key,
+            output,
+            file,
+        } => {
+            // Both this copy of the key and the one given to the signer are zeroized when dropped
+            let private_key = Zeroizing::new(fs::read_to_string(private_key)?);
+            let signer = Signer::new(app_uuid, private_key.to_string())?;
+            let time = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            let signature = DetachedSignature::sign_reader(&signer, File::open(&file)?, time)?;
+            let output = output.unwrap_or_else(|| signature_path(&file));
+            fs::write(&output, signature.to_string())?;
+
+            println!("Wrote {}", output.display());
+        }
+        Command::Verify {
+            app_uuid,
+            public_key,
+            signature,
+            file,
+        } => {
+            let verifier = Verifier::new(app_uuid, fs::read_to_string(public_key)?)?;
+            let signature =
+                DetachedSignature::load(signature.unwrap_or_else(|| signature_path(&file)))?;
+            let fingerprint =
+                signature.verify_reader(&Authenticator::from(verifier), File::open(&file)?)?;
+
+            println!(
+                "Verified {}: signed by app {} with key {fingerprint} at {}",
+                file.display(),
+                signature.app_uuid,
+                signature.time
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn signature_path(file: &Path) -> PathBuf {
+    let mut path = file.as_os_str().to_owned();
+    path.push(format!(".{FILE_EXTENSION}"));
+    path.into()
+}
@@ -0,0 +1,320 @@
+use crate::authenticator::Authenticator;
+use crate::error::Error;
+use crate::keys::KeyFingerprint;
+use crate::signable::SignableArtifact;
+use crate::signer::Signer;
+use sha2::{Digest, Sha512};
+use std::fmt;
+use std::io::Read;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The extension conventionally given to detached signature files, which are written next to the
+/// file they sign
+pub const FILE_EXTENSION: &str = "mauthsig";
+
+const HEADER: &str = "mauth-detached-signature 1";
+
+/// A signature of a file or artifact kept apart from it, such as a build artifact or a data
+/// export, so that downstream systems can check which app produced it with the MAuth public key
+/// they already trust.
+///
+/// The signature covers the SHA-512 digest of the contents, the app UUID and the time, and is
+/// made with the V2 algorithm. The file format starts with a `mauth-detached-signature 1` line,
+/// followed by one line for each field with its name and value separated by a space:
+///
+/// ```text
+/// mauth-detached-signature 1
+/// app_uuid 101c139a-236c-11ef-b5e3-125eb8485a60
+/// time 1669858655
+/// sha512 cf83e1357eefb8bdf1542850d66d8007d620e4050b5715dc83f4a921d36ce9ce47d0d13c5d85f2b0ff8318d2877eec2f63b931bd47417a81a538327af927da3e
+/// signature nKdJw7Y...
+/// ```
+///
+/// ```
+/// # use mauth_core::authenticator::Authenticator;
+/// # use mauth_core::detached::DetachedSignature;
+/// # use mauth_core::keys::{KeyPair, KeySize, PrivateKeyFormat};
+/// # use mauth_core::signer::Signer;
+/// # let signer = Signer::new("101c139a-236c-11ef-b5e3-125eb8485a60", KeyPair::generate(KeySize::Rsa2048).unwrap().private_key_pem(PrivateKeyFormat::Pkcs1).unwrap().to_string()).unwrap();
+/// let signature = DetachedSignature::sign(&signer, b"export,data\n", 1669858655).unwrap();
+/// let file = signature.to_string();
+///
+/// let parsed = DetachedSignature::parse(&file).unwrap();
+/// let result = parsed.verify(&Authenticator::from(signer.verifier()), b"export,data\n");
+/// assert!(result.is_ok());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DetachedSignature {
+    /// The UUID of the app that signed the contents
+    pub app_uuid: String,
+    /// The Unix time the contents were signed at
+    pub time: u64,
+    /// The hex encoded SHA-512 digest of the contents
+    pub digest: String,
+    /// The Base64 encoded signature
+    pub signature: String,
+}
+
+impl DetachedSignature {
+    /// Sign `contents` at the Unix time `time`
+    pub fn sign(signer: &Signer, contents: &[u8], time: u64) -> Result<Self, Error> {
+        Self::sign_digest(signer, hex::encode(Sha512::digest(contents)), time)
+    }
+
+    /// Sign everything read from `reader` at the Unix time `time`, without holding it in memory
+    pub fn sign_reader(signer: &Signer, reader: impl Read, time: u64) -> Result<Self, Error> {
+        Self::sign_digest(signer, digest(reader)?, time)
+    }
+
+    fn sign_digest(signer: &Signer, digest: String, time: u64) -> Result<Self, Error> {
+        let signable = SignableArtifact::new(&digest, time.to_string(), signer.app_uuid());
+        let signature = signer.sign_signable(2, &signable)?;
+
+        Ok(Self {
+            app_uuid: signer.app_uuid().to_owned(),
+            time,
+            digest,
+            signature,
+        })
+    }
+
+    /// Read a detached signature file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Parse the contents of a detached signature file
+    pub fn parse(contents: &str) -> Result<Self, Error> {
+        let invalid = Error::DetachedSignatureParseError;
+        let mut lines = contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty());
+        if lines.next() != Some(HEADER) {
+            return Err(invalid(format!("expected a `{HEADER}` line first")));
+        }
+
+        let (mut app_uuid, mut time, mut digest, mut signature) = (None, None, None, None);
+        for line in lines {
+            let (name, value) = line
+                .split_once(' ')
+                .ok_or_else(|| invalid(format!("expected a name and value in `{line}`")))?;
+            let field = match name {
+                "app_uuid" => &mut app_uuid,
+                "time" => &mut time,
+                "sha512" => &mut digest,
+                "signature" => &mut signature,
+                name => return Err(invalid(format!("unknown field {name}"))),
+            };
+            if field.replace(value.trim()).is_some() {
+                return Err(invalid(format!("duplicate field {name}")));
+            }
+        }
+
+        let missing = |name: &str| invalid(format!("missing field {name}"));
+        let time = time.ok_or_else(|| missing("time"))?;
+        let digest = digest.ok_or_else(|| missing("sha512"))?;
+        if digest.len() != 128 || !digest.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+            return Err(invalid(format!("invalid SHA-512 digest {digest}")));
+        }
+
+        Ok(Self {
+            app_uuid: app_uuid.ok_or_else(|| missing("app_uuid"))?.to_owned(),
+            time: time
+                .parse()
+                .ok()
+                .filter(|&time| signed_at(time).is_some())
+                .ok_or_else(|| invalid(format!("invalid time {time}")))?,
+            digest: digest.to_ascii_lowercase(),
+            signature: signature.ok_or_else(|| missing("signature"))?.to_owned(),
+        })
+    }
+
+    /// Verify that `contents` are the ones that were signed, by an app whose keys are known to
+    /// `authenticator`, with the keys that were valid at the time they were signed. Revocations
+    /// still apply from the current time. On success, the fingerprint of the key that matched is
+    /// returned. `Error::DigestMismatch` is returned if the contents have changed since they were
+    /// signed.
+    pub fn verify(
+        &self,
+        authenticator: &Authenticator,
+        contents: &[u8],
+    ) -> Result<KeyFingerprint, Error> {
+        self.verify_digest(authenticator, hex::encode(Sha512::digest(contents)))
+    }
+
+    /// Verify everything read from `reader` in the same way as `DetachedSignature::verify`,
+    /// without holding it in memory
+    pub fn verify_reader(
+        &self,
+        authenticator: &Authenticator,
+        reader: impl Read,
+    ) -> Result<KeyFingerprint, Error> {
+        self.verify_digest(authenticator, digest(reader)?)
+    }
+
+    fn verify_digest(
+        &self,
+        authenticator: &Authenticator,
+        digest: String,
+    ) -> Result<KeyFingerprint, Error> {
+        if digest != self.digest {
+            return Err(Error::DigestMismatch);
+        }
+
+        let signable = SignableArtifact::new(digest, self.time.to_string(), &self.app_uuid);
+        let signed_at = signed_at(self.time).ok_or_else(|| {
+            Error::DetachedSignatureParseError(format!("invalid time {}", self.time))
+        })?;
+        authenticator.verify_with(&self.app_uuid, |verifier| {
+            verifier.verify_signable_at(2, &signable, &self.signature, signed_at)
+        })
+    }
+}
+
+impl fmt::Display for DetachedSignature {
+    /// Format the signature as the contents of a detached signature file
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{HEADER}")?;
+        writeln!(f, "app_uuid {}", self.app_uuid)?;
+        writeln!(f, "time {}", self.time)?;
+        writeln!(f, "sha512 {}", self.digest)?;
+        writeln!(f, "signature {}", self.signature)
+    }
+}
+
+/// The time a signature was made at, or `None` if the Unix time `time` is out of range
+fn signed_at(time: u64) -> Option<SystemTime> {
+    UNIX_EPOCH.checked_add(Duration::from_secs(time))
+}
+
+fn digest(mut reader: impl Read) -> Result<String, Error> {
+    let mut hasher = Sha512::new();
+    std::io::copy(&mut reader, &mut hasher)?;
+
+    Ok(hex::encode(hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::tests::{next_signer, signer, APP_UUID};
+    use crate::verifier::{RotatingVerifier, VerificationKey};
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    const TIME: u64 = 1669858655;
+
+    fn authenticator() -> Authenticator {
+        Authenticator::from(signer().verifier())
+    }
+
+    #[test]
+    fn signatures_survive_the_file_format() {
+        let signature = DetachedSignature::sign(&signer(), b"artifact", TIME).unwrap();
+        let parsed = DetachedSignature::parse(&signature.to_string()).unwrap();
+
+        assert_eq!(parsed, signature);
+        assert_eq!((parsed.app_uuid.as_str(), parsed.time), (APP_UUID, TIME));
+        assert_eq!(
+            parsed.verify(&authenticator(), b"artifact").unwrap(),
+            signer().fingerprint()
+        );
+        assert_eq!(
+            DetachedSignature::sign_reader(&signer(), &b"artifact"[..], TIME).unwrap(),
+            signature
+        );
+    }
+
+    #[test]
+    fn verify_rejects_changed_contents_and_fields() {
+        let signature = DetachedSignature::sign(&signer(), b"artifact", TIME).unwrap();
+
+        assert!(matches!(
+            signature.verify_reader(&authenticator(), &b"artifact!"[..]),
+            Err(Error::DigestMismatch)
+        ));
+
+        let backdated = DetachedSignature {
+            time: TIME - 1,
+            ..signature.clone()
+        };
+        assert!(backdated.verify(&authenticator(), b"artifact").is_err());
+
+        let impersonated = DetachedSignature {
+            app_uuid: "5ff4257e-9c16-11e0-b048-0026bbfffe5e".to_owned(),
+            ..signature
+        };
+        assert!(matches!(
+            impersonated.verify(&authenticator(), b"artifact"),
+            Err(Error::UnknownApp(_))
+        ));
+    }
+
+    #[test]
+    fn files_verify_with_the_key_valid_when_they_were_signed() {
+        let rotated_at = UNIX_EPOCH + Duration::from_secs(TIME + 60);
+        let mut verifier = RotatingVerifier::new(APP_UUID);
+        verifier
+            .add_key(VerificationKey::new(signer().verifier()).with_not_after(rotated_at))
+            .unwrap();
+        verifier
+            .add_key(VerificationKey::new(next_signer().verifier()).with_not_before(rotated_at))
+            .unwrap();
+        let authenticator =
+            Authenticator::new(HashMap::from([(APP_UUID.to_owned(), Arc::new(verifier))]));
+
+        let before = DetachedSignature::sign(&signer(), b"artifact", TIME).unwrap();
+        assert_eq!(
+            before.verify(&authenticator, b"artifact").unwrap(),
+            signer().fingerprint()
+        );
+
+        let after = DetachedSignature::sign(&signer(), b"artifact", TIME + 120).unwrap();
+        assert!(matches!(
+            after.verify(&authenticator, b"artifact"),
+            Err(Error::NoMatchingKey { .. })
+        ));
+        let after = DetachedSignature::sign(&next_signer(), b"artifact", TIME + 120).unwrap();
+        assert_eq!(
+            after.verify(&authenticator, b"artifact").unwrap(),
+            next_signer().fingerprint()
+        );
+    }
+
+    #[test]
+    fn parse_rejects_malformed_files() {
+        let signature = DetachedSignature::sign(&signer(), b"artifact", TIME)
+            .unwrap()
+            .to_string();
+
+        for contents in [
+            signature.replacen(HEADER, "mauth-detached-signature 2", 1),
+            signature.replacen("time ", "clock ", 1),
+            signature.replacen("time 1669858655", "time soon", 1),
+            signature.replacen("time 1669858655", &format!("time {}", u64::MAX), 1),
+            signature.replacen("sha512 ", "sha512 00", 1),
+            format!("{signature}app_uuid {APP_UUID}\n"),
+            signature.lines().take(4).collect::<Vec<_>>().join("\n"),
+        ] {
+            assert!(matches!(
+                DetachedSignature::parse(&contents),
+                Err(Error::DetachedSignatureParseError(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn verify_rejects_out_of_range_times() {
+        let signature = DetachedSignature {
+            time: u64::MAX,
+            ..DetachedSignature::sign(&signer(), b"artifact", TIME).unwrap()
+        };
+
+        assert!(matches!(
+            signature.verify(&authenticator(), b"artifact"),
+            Err(Error::DetachedSignatureParseError(_))
+        ));
+    }
+}
@@ -1,3 +1,4 @@
+use crate::keys::{KeyFingerprint, KeyPolicyViolation};
 use thiserror::Error;
 
 /// All of the possible errors that can happen while performing mauth operations
@@ -18,10 +19,151 @@ pub enum Error {
     /// An algorithm failure occurred while trying to sign a request
     #[error("RSA algorithm error: {0}")]
     RsaSignError(#[from] rsa::Error),
-    /// An algorithm failure occurred while trying to verify a request
-    #[error("Unable to verify RSA signature: {0}")]
-    SignatureVerifyError(#[from] rsa::signature::Error),
+    /// The signature did not match the request, or was not a valid signature for the key
+    #[error("Unable to verify RSA signature with key {fingerprint}: {source}")]
+    SignatureVerifyError {
+        /// The fingerprint of the public key the signature was checked against
+        fingerprint: KeyFingerprint,
+        /// The underlying verification failure
+        #[source]
+        source: rsa::signature::Error,
+    },
     /// A base64 error was encountered while attempting to verify a v1 signature
     #[error("Unable to decode base64-encoded signature: {0}")]
     SignatureDecodeError(#[from] base64::DecodeError),
+    /// A key was provided for a different app than the one it is being used for
+    #[error("Key belongs to app {actual}, expected app {expected}")]
+    AppUuidMismatch {
+        /// The app UUID the key was expected to belong to
+        expected: String,
+        /// The app UUID the key actually belongs to
+        actual: String,
+    },
+    /// None of the app's keys are within their validity period
+    #[error("No key for app {0} is currently valid")]
+    NoValidKey(String),
+    /// The signature did not match any of the app's currently valid keys
+    #[error("Signature for app {app_uuid} did not match any of the keys {fingerprints:?}")]
+    NoMatchingKey {
+        /// The app the signature claimed to come from
+        app_uuid: String,
+        /// The fingerprints of the keys that were tried, in order
+        fingerprints: Vec<KeyFingerprint>,
+    },
+    /// A route or default referred to a signer label that has not been registered
+    #[error("No signer is registered with the label {0}")]
+    UnknownSigner(String),
+    /// A request claimed to come from an app whose keys are not known
+    #[error("No keys are known for app {0}")]
+    UnknownApp(String),
+    /// A file could not be read or written
+    #[error("I/O error: {0}")]
+    IoError(#[from] std::io::Error),
+    /// A key cache file did not match its checksum, as it has been corrupted
+    #[error("Key cache file {} does not match its checksum", .0.display())]
+    CacheIntegrityError(std::path::PathBuf),
+    /// A trust store could not be parsed
+    #[error("Unable to parse trust store: {0}")]
+    TrustStoreParseError(String),
+    /// One or more entries of a trust store were invalid
+    #[error("Invalid trust store entries: {}", join(.0))]
+    InvalidTrustStoreEntries(Vec<TrustStoreEntryError>),
+    /// A trust store entry was well formed, but its values were not valid together
+    #[error("Invalid trust store entry for app {app_uuid:?}: {reason}")]
+    InvalidTrustStoreEntry {
+        /// The app UUID of the entry
+        app_uuid: String,
+        /// What was wrong with the entry
+        reason: String,
+    },
+    /// A key fingerprint was not 32 bytes of hex
+    #[error("Invalid key fingerprint: {0}")]
+    InvalidFingerprint(String),
+    /// The signature was made with a key that has been revoked
+    #[error("Key {fingerprint} of app {app_uuid} has been revoked")]
+    KeyRevoked {
+        /// The app the signature came from
+        app_uuid: String,
+        /// The fingerprint of the revoked key
+        fingerprint: KeyFingerprint,
+    },
+    /// A line of a revocation list could not be parsed
+    #[error("Unable to parse revocation list line {line}: {reason}")]
+    RevocationListParseError {
+        /// The line number, starting from one
+        line: usize,
+        /// What was wrong with the line
+        reason: String,
+    },
+    /// A key could not be encoded while exporting it
+    #[error("Unable to encode RSA key: {0}")]
+    KeyEncodeError(#[source] rsa::pkcs8::Error),
+    /// The provided key does not meet the minimum strength required by the key policy
+    #[error("RSA key rejected by key policy: {0}")]
+    KeyPolicyViolation(#[from] KeyPolicyViolation),
+    /// A request or response carried no MAuth authentication headers
+    #[error("No MAuth authentication headers were provided")]
+    MissingAuthentication,
+    /// A MAuth authentication or time header was present but malformed
+    #[error("Invalid MAuth header: {0}")]
+    InvalidAuthenticationHeader(String),
+    /// The MAuth time header was too far from the current time
+    #[error("MAuth time {0} is outside of the allowed clock skew")]
+    TimestampOutOfRange(u64),
+    /// The authenticated app is not on the allowlist of the route it requested
+    #[error("App {0} is not allowed to access this route")]
+    AppNotAllowed(String),
+    /// A route pattern was not an optional HTTP method followed by a path
+    #[error("Invalid route pattern: {0}")]
+    InvalidRoutePattern(String),
+    /// A response was signed by a different app than the one the request was expected to reach
+    #[error("Response was signed by app {actual} instead of {expected}")]
+    UnexpectedResponder {
+        /// The app the response was expected to be signed by
+        expected: String,
+        /// The app that signed the response
+        actual: String,
+    },
+    /// No signer was selected for an outgoing request
+    #[error("No signer is configured for requests to {0}")]
+    NoSignerSelected(String),
+    /// An outgoing request was to a host that is not on the signing allowlist
+    #[error("Requests to {0} are not allowed to be signed")]
+    HostNotAllowed(String),
+    /// A host pattern was not an optional scheme followed by a host name
+    #[error("Invalid host pattern: {0}")]
+    InvalidHostPattern(String),
+    /// A body was larger than the configured limit and was not read
+    #[error("Body exceeds the limit of {0} bytes")]
+    BodyTooLarge(usize),
+    /// A body could not be read in order to sign or verify it
+    #[error("Unable to read body: {0}")]
+    BodyReadError(#[source] Box<dyn std::error::Error + Send + Sync>),
+    /// A detached signature file could not be parsed
+    #[error("Unable to parse detached signature: {0}")]
+    DetachedSignatureParseError(String),
+    /// The signed contents do not match the digest recorded in their detached signature
+    #[error("Contents do not match the digest of the detached signature")]
+    DigestMismatch,
+}
+
+/// The reason a single trust store entry was rejected
+#[derive(Debug, Error)]
+#[error("entry {index} for app {app_uuid}: {source}")]
+pub struct TrustStoreEntryError {
+    /// The position of the entry in the trust store, starting from zero
+    pub index: usize,
+    /// The app UUID of the entry
+    pub app_uuid: String,
+    /// Why the entry was rejected
+    #[source]
+    pub source: Box<Error>,
+}
+
+fn join(errors: &[TrustStoreEntryError]) -> String {
+    errors
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<String>>()
+        .join("; ")
 }
@@ -0,0 +1,428 @@
+use crate::error::Error;
+use crate::http::{rejection_status, AuthenticatedApp, RequestAuthenticator, SigningVersions};
+use crate::policy::{AuthPolicy, Enforcement};
+use crate::registry::SignerSelector;
+use crate::tower_client::SigningLayer;
+use crate::tower_server::{authenticate_blocking, claimed_app_uuid, read_body};
+use bytes::Bytes;
+use http::{Request, Response, StatusCode};
+use http_body::Body;
+use http_body_util::Full;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tonic::Status;
+use tower_layer::Layer;
+use tower_service::Service;
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
+
+/// A tower layer for tonic channels that signs each gRPC call with MAuth, using the method path,
+/// such as `/package.Service/Method`, as the path and the encoded request messages as the body.
+///
+/// This is a layer rather than a tonic interceptor because interceptors cannot see the encoded
+/// messages. The request stream is buffered, up to a limit, before the call is sent, so it suits
+/// unary and server streaming calls but not client streams that stay open. The MAuth headers are
+/// sent as request metadata.
+///
+/// The URI of a call only has a host once tonic's channel has added the endpoint's origin, which
+/// happens inside the layers it is given. Signers are therefore selected by method path alone,
+/// and a `http::HostAllowlist` cannot be used. Calls that cannot be signed fail with a
+/// `Code::Unknown` status whose source is the `error::Error`.
+///
+/// ```
+/// # use mauth_core::grpc::GrpcSigningLayer;
+/// # use mauth_core::keys::{KeyPair, KeySize, PrivateKeyFormat};
+/// # use mauth_core::signer::Signer;
+/// # let signer = Signer::new("101c139a-236c-11ef-b5e3-125eb8485a60", KeyPair::generate(KeySize::Rsa2048).unwrap().private_key_pem(PrivateKeyFormat::Pkcs1).unwrap().to_string()).unwrap();
+/// let layer = GrpcSigningLayer::new(signer).with_body_limit(1024 * 1024);
+/// ```
+#[derive(Debug, Clone)]
+pub struct GrpcSigningLayer {
+    layer: SigningLayer,
+}
+
+impl GrpcSigningLayer {
+    /// Initialize a layer that signs calls with the signer chosen by `selector` using V2,
+    /// buffering requests of up to `http::DEFAULT_BODY_LIMIT` bytes.
+    pub fn new(selector: impl SignerSelector + 'static) -> Self {
+        Self {
+            layer: SigningLayer::new(selector),
+        }
+    }
+
+    /// Set the MAuth protocol versions calls are signed with
+    pub fn with_versions(mut self, versions: SigningVersions) -> Self {
+        self.layer = self.layer.with_versions(versions);
+        self
+    }
+
+    /// Set the largest encoded request, in bytes, that will be buffered for signing
+    pub fn with_body_limit(mut self, body_limit: usize) -> Self {
+        self.layer = self.layer.with_body_limit(body_limit);
+        self
+    }
+}
+
+impl<S> Layer<S> for GrpcSigningLayer {
+    type Service = GrpcSigningService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        GrpcSigningService {
+            inner,
+            layer: self.layer.clone(),
+        }
+    }
+}
+
+/// The service created by `GrpcSigningLayer`
+#[derive(Debug, Clone)]
+pub struct GrpcSigningService<S> {
+    inner: S,
+    layer: SigningLayer,
+}
+
+impl<S, B, ResBody> Service<Request<B>> for GrpcSigningService<S>
+where
+    S: Service<Request<tonic::body::Body>, Response = Response<ResBody>> + Clone + Send + 'static,
+    S::Error: Into<BoxError>,
+    S::Future: Send,
+    B: Body + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+{
+    type Response = Response<ResBody>;
+    type Error = BoxError;
+    type Future = BoxFuture<Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        // The clone is not guaranteed to be ready, so the ready service is taken for this call
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let layer = self.layer.clone();
+
+        Box::pin(async move {
+            let (parts, body) = layer.buffer_and_sign(request).await?;
+            let body = tonic::body::Body::new(Full::new(body));
+
+            inner
+                .call(Request::from_parts(parts, body))
+                .await
+                .map_err(Into::into)
+        })
+    }
+}
+
+/// A tower layer for tonic servers that authenticates MAuth signed gRPC calls, in the same way as
+/// `tower_server::AuthenticationLayer` but answering failures with a gRPC status.
+///
+/// The request stream is buffered, up to a limit, and verified on tokio's blocking thread pool.
+/// Calls to exempt methods are passed on with their original stream, without being buffered.
+/// Calls that pass have an `http::AuthenticatedApp` inserted into their extensions, which
+/// handlers can read with `grpc::authenticated_app`. Routes are handled according to a
+/// `policy::AuthPolicy` matched against the method path, so health checks can be exempted with
+/// `/grpc.health.v1.Health/**`. Failures are answered with `Code::Unauthenticated`, or
+/// `Code::PermissionDenied` for apps that are not on a route's allowlist and
+/// `Code::ResourceExhausted` for requests over the limit.
+///
+/// ```
+/// # use mauth_core::authenticator::Authenticator;
+/// # use mauth_core::grpc::GrpcAuthenticationLayer;
+/// # use mauth_core::http::RequestAuthenticator;
+/// # use mauth_core::policy::{AuthPolicy, Enforcement};
+/// # use mauth_core::verifier::RotatingVerifier;
+/// # use std::collections::HashMap;
+/// # use std::sync::Arc;
+/// # let verifiers: HashMap<String, Arc<RotatingVerifier>> = HashMap::new();
+/// let mut policy = AuthPolicy::default();
+/// policy.route("/grpc.health.v1.Health/**", Enforcement::Exempt).unwrap();
+///
+/// let layer = GrpcAuthenticationLayer::new(RequestAuthenticator::new(Authenticator::new(verifiers)))
+///     .with_policy(policy);
+/// ```
+#[derive(Debug, Clone)]
+pub struct GrpcAuthenticationLayer {
+    authenticator: Arc<RequestAuthenticator>,
+    policy: Arc<AuthPolicy>,
+    body_limit: usize,
+}
+
+impl GrpcAuthenticationLayer {
+    /// Initialize a layer that authenticates calls with `authenticator`, buffering requests of up
+    /// to `http::DEFAULT_BODY_LIMIT` bytes.
+    pub fn new(authenticator: RequestAuthenticator) -> Self {
+        Self {
+            authenticator: Arc::new(authenticator),
+            policy: Arc::default(),
+            body_limit: crate::http::DEFAULT_BODY_LIMIT,
+        }
+    }
+
+    /// Set the policy deciding how failures are handled on each method
+    pub fn with_policy(mut self, policy: AuthPolicy) -> Self {
+        self.policy = Arc::new(policy);
+        self
+    }
+
+    /// Set the largest encoded request, in bytes, that will be buffered for verification
+    pub fn with_body_limit(mut self, body_limit: usize) -> Self {
+        self.body_limit = body_limit;
+        self
+    }
+}
+
+impl<S> Layer<S> for GrpcAuthenticationLayer {
+    type Service = GrpcAuthenticationService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        GrpcAuthenticationService {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// The service created by `GrpcAuthenticationLayer`
+#[derive(Debug, Clone)]
+pub struct GrpcAuthenticationService<S> {
+    inner: S,
+    layer: GrpcAuthenticationLayer,
+}
+
+impl<S, B, ResBody> Service<Request<B>> for GrpcAuthenticationService<S>
+where
+    S: Service<Request<tonic::body::Body>, Response = Response<ResBody>> + Clone + Send + 'static,
+    S::Future: Send,
+    B: Body<Data = Bytes> + Send + 'static,
+    B::Error: Into<BoxError>,
+    ResBody: Default,
+{
+    type Response = Response<ResBody>;
+    type Error = S::Error;
+    type Future = BoxFuture<Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        // The clone is not guaranteed to be ready, so the ready service is taken for this call
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let GrpcAuthenticationLayer {
+            authenticator,
+            policy,
+            body_limit,
+        } = self.layer.clone();
+
+        Box::pin(async move {
+            let (parts, body) = request.into_parts();
+            if policy.enforcement(parts.method.as_str(), parts.uri.path()) == Enforcement::Exempt {
+                let body = tonic::body::Body::new(body);
+                return inner.call(Request::from_parts(parts, body)).await;
+            }
+            let body = match read_body(body, body_limit).await {
+                Ok(body) => body,
+                Err(e) => return Ok(grpc_status(&e).into_http()),
+            };
+
+            let Ok((mut parts, body, result)) =
+                authenticate_blocking(authenticator, parts, body).await
+            else {
+                return Ok(Status::internal("MAuth authentication failed").into_http());
+            };
+            let app_uuid = result
+                .is_err()
+                .then(|| claimed_app_uuid(&parts.headers))
+                .flatten();
+            match policy.apply(
+                parts.method.as_str(),
+                parts.uri.path(),
+                app_uuid.as_deref(),
+                result,
+            ) {
+                Ok(Some(app)) => {
+                    parts.extensions.insert(app);
+                }
+                Ok(None) => {}
+                Err(e) => return Ok(grpc_status(&e).into_http()),
+            }
+
+            let body = tonic::body::Body::new(Full::new(body));
+            inner.call(Request::from_parts(parts, body)).await
+        })
+    }
+}
+
+/// The app that a call was authenticated as by `GrpcAuthenticationLayer`, or an
+/// `Code::Unauthenticated` status to return if it was not authenticated. This includes calls that
+/// failed authentication on a method in shadow mode.
+///
+/// ```
+/// # use mauth_core::grpc::authenticated_app;
+/// # use tonic::{Code, Request};
+/// let request = Request::new(());
+/// assert_eq!(authenticated_app(&request).unwrap_err().code(), Code::Unauthenticated);
+/// ```
+pub fn authenticated_app<T>(request: &tonic::Request<T>) -> Result<&AuthenticatedApp, Status> {
+    request
+        .extensions()
+        .get::<AuthenticatedApp>()
+        .ok_or_else(|| grpc_status(&Error::MissingAuthentication))
+}
+
+/// The gRPC status to fail a call with when it could not be authenticated, corresponding to the
+/// HTTP status from `http::rejection_status`
+pub fn grpc_status(error: &Error) -> Status {
+    match rejection_status(error) {
+        StatusCode::FORBIDDEN => Status::permission_denied("MAuth app is not allowed"),
+        StatusCode::PAYLOAD_TOO_LARGE => Status::resource_exhausted("Request is too large"),
+        StatusCode::BAD_REQUEST => Status::invalid_argument("Unable to read request"),
+        StatusCode::INTERNAL_SERVER_ERROR => Status::internal("MAuth authentication failed"),
+        _ => Status::unauthenticated("MAuth authentication failed"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::tests::authenticator;
+    use crate::keys::tests::{signer, APP_UUID};
+    use http::uri::PathAndQuery;
+    use std::convert::Infallible;
+    use tonic::server::UnaryService;
+    use tonic::Code;
+    use tonic_prost::ProstCodec;
+    use tower::service_fn;
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    struct Echo {
+        #[prost(string, tag = "1")]
+        message: String,
+    }
+
+    /// Echoes the message of each call, prefixed with the app UUID that signed it, if any
+    struct EchoService;
+
+    impl UnaryService<Echo> for EchoService {
+        type Response = Echo;
+        type Future = BoxFuture<Result<tonic::Response<Echo>, Status>>;
+
+        fn call(&mut self, request: tonic::Request<Echo>) -> Self::Future {
+            let app_uuid =
+                authenticated_app(&request).map_or("none".to_owned(), |app| app.app_uuid.clone());
+            Box::pin(async move {
+                let message = format!("{app_uuid} {}", request.into_inner().message);
+                Ok(tonic::Response::new(Echo { message }))
+            })
+        }
+    }
+
+    fn server(
+        layer: GrpcAuthenticationLayer,
+    ) -> impl Service<
+        Request<tonic::body::Body>,
+        Response = Response<tonic::body::Body>,
+        Error = Infallible,
+        Future = impl Send,
+    > + Clone
+           + Send
+           + 'static {
+        layer.layer(service_fn(|request: Request<tonic::body::Body>| async {
+            let mut grpc = tonic::server::Grpc::new(ProstCodec::<Echo, Echo>::default());
+            Ok::<_, Infallible>(grpc.unary(EchoService, request).await)
+        }))
+    }
+
+    async fn echo<T>(channel: T, message: &str) -> Result<String, Status>
+    where
+        T: tonic::client::GrpcService<tonic::body::Body>,
+        T::Error: Into<BoxError>,
+        T::ResponseBody: Body<Data = bytes::Bytes> + Send + 'static,
+        <T::ResponseBody as Body>::Error: Into<BoxError> + Send,
+    {
+        let mut client = tonic::client::Grpc::new(channel);
+        client
+            .ready()
+            .await
+            .map_err(|e| Status::unknown(e.into().to_string()))?;
+        let request = tonic::Request::new(Echo {
+            message: message.to_owned(),
+        });
+        let response: tonic::Response<Echo> = client
+            .unary(
+                request,
+                PathAndQuery::from_static("/echo.Echo/Echo"),
+                ProstCodec::default(),
+            )
+            .await?;
+
+        Ok(response.into_inner().message)
+    }
+
+    fn authentication() -> GrpcAuthenticationLayer {
+        GrpcAuthenticationLayer::new(RequestAuthenticator::new(authenticator()))
+    }
+
+    #[tokio::test]
+    async fn signed_calls_reach_the_handler() {
+        let channel = GrpcSigningLayer::new(signer()).layer(server(authentication()));
+
+        let message = echo(channel, "hello").await.unwrap();
+
+        assert_eq!(message, format!("{APP_UUID} hello"));
+    }
+
+    #[tokio::test]
+    async fn unsigned_calls_are_unauthenticated() {
+        let status = echo(server(authentication()), "hello").await.unwrap_err();
+
+        assert_eq!(status.code(), Code::Unauthenticated);
+    }
+
+    #[tokio::test]
+    async fn policy_applies_to_method_paths() {
+        let mut policy = AuthPolicy::default();
+        policy.allow_apps("/echo.Echo/*", ["other-app"]).unwrap();
+        let channel =
+            GrpcSigningLayer::new(signer()).layer(server(authentication().with_policy(policy)));
+        let status = echo(channel, "hello").await.unwrap_err();
+        assert_eq!(status.code(), Code::PermissionDenied);
+
+        let mut policy = AuthPolicy::default();
+        policy.route("/echo.Echo/**", Enforcement::Exempt).unwrap();
+        let message = echo(server(authentication().with_policy(policy)), "hello")
+            .await
+            .unwrap();
+        assert_eq!(message, "none hello");
+    }
+
+    #[tokio::test]
+    async fn exempt_methods_are_not_buffered() {
+        let mut policy = AuthPolicy::default();
+        policy.route("/echo.Echo/**", Enforcement::Exempt).unwrap();
+        let layer = authentication().with_body_limit(1).with_policy(policy);
+
+        let message = echo(server(layer), "hello").await.unwrap();
+
+        assert_eq!(message, "none hello");
+    }
+
+    #[test]
+    fn statuses_correspond_to_http_rejections() {
+        assert_eq!(
+            grpc_status(&Error::BodyTooLarge(1)).code(),
+            Code::ResourceExhausted
+        );
+        assert_eq!(
+            grpc_status(&Error::TimestampOutOfRange(1)).code(),
+            Code::Unauthenticated
+        );
+    }
+}
@@ -0,0 +1,792 @@
+use crate::authenticator::Authenticator;
+use crate::error::Error;
+use crate::keys::KeyFingerprint;
+use crate::signer::Signer;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The V2 authentication header, holding `MWSV2 {app_uuid}:{signature};`
+pub const MCC_AUTHENTICATION: &str = "mcc-authentication";
+/// The V2 time header, holding the Unix time the signature was made at
+pub const MCC_TIME: &str = "mcc-time";
+/// The V1 authentication header, holding `MWS {app_uuid}:{signature}`
+pub const X_MWS_AUTHENTICATION: &str = "x-mws-authentication";
+/// The V1 time header, holding the Unix time the signature was made at
+pub const X_MWS_TIME: &str = "x-mws-time";
+
+/// Every MAuth authentication and time header
+pub const MAUTH_HEADERS: [&str; 4] = [
+    MCC_AUTHENTICATION,
+    MCC_TIME,
+    X_MWS_AUTHENTICATION,
+    X_MWS_TIME,
+];
+
+/// The default maximum difference between the MAuth time header and the current time
+pub const DEFAULT_MAX_TIME_SKEW: Duration = Duration::from_secs(300);
+
+/// The default limit on the size of a request body buffered for verification
+pub const DEFAULT_BODY_LIMIT: usize = 10 * 1024 * 1024;
+
+const V2_PREFIX: &str = "MWSV2 ";
+const V1_PREFIX: &str = "MWS ";
+
+/// The MAuth protocol versions that outgoing requests and responses are signed with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SigningVersions {
+    /// Only sign with V1, for servers that do not support V2
+    V1,
+    /// Only sign with V2
+    #[default]
+    V2,
+    /// Sign with both versions, for servers that are migrating from V1 to V2
+    Both,
+}
+
+impl SigningVersions {
+    fn versions(self) -> &'static [u8] {
+        match self {
+            SigningVersions::V1 => &[1],
+            SigningVersions::V2 => &[2],
+            SigningVersions::Both => &[1, 2],
+        }
+    }
+}
+
+/// What the client integrations do with requests to hosts that are not on a `HostAllowlist`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnlistedHosts {
+    /// Fail the request with `Error::HostNotAllowed`
+    #[default]
+    Refuse,
+    /// Send the request without MAuth headers, removing any it already has
+    SendUnsigned,
+}
+
+/// The schemes and hosts that the client integrations may send signed requests to, so that valid
+/// signatures are not handed to third parties who could replay them within the time skew.
+///
+/// Patterns are a host name optionally preceded by a scheme, such as `https://api.example.com`.
+/// A leading `*.` matches any subdomain, but not the domain itself, and hosts are matched without
+/// regard to case. Requests without a host, such as a relative URI, never match.
+///
+/// ```
+/// # use mauth_core::http::{HostAllowlist, UnlistedHosts};
+/// let mut allowlist = HostAllowlist::new(UnlistedHosts::Refuse);
+/// allowlist.allow("https://*.example.com").unwrap();
+/// allowlist.allow("localhost").unwrap();
+///
+/// assert!(allowlist.is_allowed(Some("https"), Some("api.example.com")));
+/// assert!(!allowlist.is_allowed(Some("http"), Some("api.example.com")));
+/// assert!(allowlist.is_allowed(Some("http"), Some("localhost")));
+/// assert!(!allowlist.is_allowed(Some("https"), Some("example.org")));
+/// ```
+#[derive(Debug, Clone)]
+pub struct HostAllowlist {
+    hosts: Vec<HostPattern>,
+    unlisted: UnlistedHosts,
+}
+
+#[derive(Debug, Clone)]
+struct HostPattern {
+    scheme: Option<String>,
+    host: String,
+    subdomains: bool,
+}
+
+impl HostAllowlist {
+    /// Initialize an empty allowlist, handling every request as `unlisted` says
+    pub fn new(unlisted: UnlistedHosts) -> Self {
+        Self {
+            hosts: Vec::new(),
+            unlisted,
+        }
+    }
+
+    /// What is done with requests to hosts that are not on the allowlist
+    pub fn unlisted(&self) -> UnlistedHosts {
+        self.unlisted
+    }
+
+    /// Allow signed requests to the hosts matching `pattern`
+    pub fn allow(&mut self, pattern: &str) -> Result<(), Error> {
+        let invalid = || Error::InvalidHostPattern(pattern.to_owned());
+        let (scheme, host) = match pattern.split_once("://") {
+            Some((scheme, host)) if !scheme.is_empty() => (Some(scheme.to_ascii_lowercase()), host),
+            Some(_) => return Err(invalid()),
+            None => (None, pattern),
+        };
+        let (host, subdomains) = match host.strip_prefix("*.") {
+            Some(domain) => (domain, true),
+            None => (host, false),
+        };
+        if host.is_empty() || host.contains(['/', '*', ':', '?', '#', '@']) {
+            return Err(invalid());
+        }
+
+        self.hosts.push(HostPattern {
+            scheme,
+            host: host.to_ascii_lowercase(),
+            subdomains,
+        });
+        Ok(())
+    }
+
+    /// Whether requests with `scheme` to `host` may be signed
+    pub fn is_allowed(&self, scheme: Option<&str>, host: Option<&str>) -> bool {
+        let Some(host) = host else {
+            return false;
+        };
+        let host = host.to_ascii_lowercase();
+        self.hosts.iter().any(|pattern| {
+            let scheme_matches = pattern
+                .scheme
+                .as_deref()
+                .is_none_or(|expected| scheme.is_some_and(|s| s.eq_ignore_ascii_case(expected)));
+            let host_matches = if pattern.subdomains {
+                host.strip_suffix(&pattern.host)
+                    .is_some_and(|subdomain| subdomain.len() > 1 && subdomain.ends_with('.'))
+            } else {
+                host == pattern.host
+            };
+            scheme_matches && host_matches
+        })
+    }
+
+    /// Whether a request should be signed, `Ok(false)` meaning it should be sent unsigned, or
+    /// `Error::HostNotAllowed` naming `origin` if it should be refused
+    #[cfg(any(feature = "tower", feature = "reqwest"))]
+    pub(crate) fn should_sign(
+        &self,
+        scheme: Option<&str>,
+        host: Option<&str>,
+        origin: impl FnOnce() -> String,
+    ) -> Result<bool, Error> {
+        if self.is_allowed(scheme, host) {
+            return Ok(true);
+        }
+        match self.unlisted {
+            UnlistedHosts::Refuse => Err(Error::HostNotAllowed(origin())),
+            UnlistedHosts::SendUnsigned => Ok(false),
+        }
+    }
+}
+
+/// Sign a request at the Unix time `time`, returning the authentication and time headers to add
+/// to it for each of `versions`. The `path` must be the path of the request URI as it will be sent,
+/// and `query` its query string without the leading `?`.
+///
+/// ```
+/// # use mauth_core::http::{sign_request, SigningVersions, MCC_AUTHENTICATION};
+/// # use mauth_core::keys::{KeyPair, KeySize, PrivateKeyFormat};
+/// # use mauth_core::signer::Signer;
+/// # let signer = Signer::new("101c139a-236c-11ef-b5e3-125eb8485a60", KeyPair::generate(KeySize::Rsa2048).unwrap().private_key_pem(PrivateKeyFormat::Pkcs1).unwrap().to_string()).unwrap();
+/// let headers = sign_request(&signer, SigningVersions::Both, "GET", "/item", "page=2", b"", 1669858655).unwrap();
+///
+/// assert_eq!(headers.len(), 4);
+/// assert!(headers.iter().any(|(name, _)| *name == MCC_AUTHENTICATION));
+/// ```
+pub fn sign_request(
+    signer: &Signer,
+    versions: SigningVersions,
+    verb: &str,
+    path: &str,
+    query: &str,
+    body: &[u8],
+    time: u64,
+) -> Result<Vec<(&'static str, String)>, Error> {
+    signed_headers(signer, versions, time, |version, time| {
+        signer.sign_string(version, verb, path, query, body, time)
+    })
+}
+
+/// Sign a response at the Unix time `time`, returning the authentication and time headers to add
+/// to it for each of `versions`.
+///
+/// ```
+/// # use mauth_core::http::{sign_response, SigningVersions, MCC_TIME};
+/// # use mauth_core::keys::{KeyPair, KeySize, PrivateKeyFormat};
+/// # use mauth_core::signer::Signer;
+/// # let signer = Signer::new("101c139a-236c-11ef-b5e3-125eb8485a60", KeyPair::generate(KeySize::Rsa2048).unwrap().private_key_pem(PrivateKeyFormat::Pkcs1).unwrap().to_string()).unwrap();
+/// let headers = sign_response(&signer, SigningVersions::V2, 200, b"{}", 1669858655).unwrap();
+///
+/// assert_eq!(headers.len(), 2);
+/// assert!(headers.contains(&(MCC_TIME, "1669858655".to_owned())));
+/// ```
+pub fn sign_response(
+    signer: &Signer,
+    versions: SigningVersions,
+    status: u16,
+    body: &[u8],
+    time: u64,
+) -> Result<Vec<(&'static str, String)>, Error> {
+    signed_headers(signer, versions, time, |version, time| {
+        signer.sign_response(version, status, body, time)
+    })
+}
+
+fn signed_headers(
+    signer: &Signer,
+    versions: SigningVersions,
+    time: u64,
+    sign: impl Fn(u8, &str) -> Result<String, Error>,
+) -> Result<Vec<(&'static str, String)>, Error> {
+    let app_uuid = signer.app_uuid();
+    let time = time.to_string();
+    let mut headers = Vec::with_capacity(4);
+    for &version in versions.versions() {
+        let signature = sign(version, &time)?;
+        match version {
+            1 => {
+                headers.push((
+                    X_MWS_AUTHENTICATION,
+                    format!("{V1_PREFIX}{app_uuid}:{signature}"),
+                ));
+                headers.push((X_MWS_TIME, time.clone()));
+            }
+            _ => {
+                headers.push((
+                    MCC_AUTHENTICATION,
+                    format!("{V2_PREFIX}{app_uuid}:{signature};"),
+                ));
+                headers.push((MCC_TIME, time.clone()));
+            }
+        }
+    }
+
+    Ok(headers)
+}
+
+/// Sign a request in the same way as `sign_request` at the current time, returning typed headers
+/// for the client integrations to insert in place of any already on the request
+#[cfg(any(feature = "tower", feature = "reqwest", feature = "tungstenite"))]
+pub(crate) fn signature_headers(
+    signer: &Signer,
+    versions: SigningVersions,
+    verb: &str,
+    path: &str,
+    query: &str,
+    body: &[u8],
+) -> Result<Vec<(http::HeaderName, http::HeaderValue)>, Error> {
+    let time = unix_time(SystemTime::now());
+    typed_headers(sign_request(
+        signer, versions, verb, path, query, body, time,
+    )?)
+}
+
+/// Sign a response in the same way as `sign_response` at the current time, returning typed headers
+/// for the server integrations to insert
+#[cfg(feature = "tower")]
+pub(crate) fn response_signature_headers(
+    signer: &Signer,
+    versions: SigningVersions,
+    status: u16,
+    body: &[u8],
+) -> Result<Vec<(http::HeaderName, http::HeaderValue)>, Error> {
+    let time = unix_time(SystemTime::now());
+    typed_headers(sign_response(signer, versions, status, body, time)?)
+}
+
+#[cfg(any(feature = "tower", feature = "reqwest", feature = "tungstenite"))]
+fn typed_headers(
+    headers: Vec<(&'static str, String)>,
+) -> Result<Vec<(http::HeaderName, http::HeaderValue)>, Error> {
+    headers
+        .into_iter()
+        .map(|(name, value)| {
+            let value = http::HeaderValue::try_from(value)
+                .map_err(|e| Error::InvalidAuthenticationHeader(e.to_string()))?;
+            Ok((http::HeaderName::from_static(name), value))
+        })
+        .collect()
+}
+
+/// The number of whole seconds between the Unix epoch and `time`
+pub(crate) fn unix_time(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// The app a request was authenticated as, which the server integrations leave in the request
+/// extensions for handlers to read.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AuthenticatedApp {
+    /// The UUID of the app that signed the request
+    pub app_uuid: String,
+    /// The fingerprint of the key the signature was verified with
+    pub key_fingerprint: KeyFingerprint,
+    /// The MAuth protocol version the request was signed with
+    pub version: u8,
+}
+
+/// The MAuth headers of a request, parsed but not yet verified
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MAuthHeaders {
+    /// The MAuth protocol version of the headers
+    pub version: u8,
+    /// The UUID of the app the request claims to come from
+    pub app_uuid: String,
+    /// The Base64 encoded signature
+    pub signature: String,
+    /// The Unix time the request claims to have been signed at
+    pub time: u64,
+}
+
+impl MAuthHeaders {
+    /// Parse the MAuth headers of a request, looking each header up by its lowercase name with
+    /// `header`. The V2 headers are used if they are present, otherwise the V1 headers are used
+    /// unless `v2_only` is set. `Error::MissingAuthentication` is returned if there are none.
+    ///
+    /// ```
+    /// # use mauth_core::http::{MAuthHeaders, MCC_AUTHENTICATION, MCC_TIME};
+    /// let headers = MAuthHeaders::parse(
+    ///     |name| match name {
+    ///         MCC_AUTHENTICATION => Some("MWSV2 101c139a-236c-11ef-b5e3-125eb8485a60:c2lnbmF0dXJl;"),
+    ///         MCC_TIME => Some("1669858655"),
+    ///         _ => None,
+    ///     },
+    ///     false,
+    /// )
+    /// .unwrap();
+    /// assert_eq!(headers.version, 2);
+    /// assert_eq!(headers.app_uuid, "101c139a-236c-11ef-b5e3-125eb8485a60");
+    /// ```
+    pub fn parse<'a>(
+        header: impl Fn(&str) -> Option<&'a str>,
+        v2_only: bool,
+    ) -> Result<Self, Error> {
+        if let Some(authentication) = header(MCC_AUTHENTICATION) {
+            let credentials = authentication
+                .strip_prefix(V2_PREFIX)
+                .and_then(|credentials| credentials.strip_suffix(';'))
+                .ok_or_else(|| invalid_header(MCC_AUTHENTICATION))?;
+            return Self::from_credentials(
+                2,
+                credentials,
+                MCC_AUTHENTICATION,
+                header(MCC_TIME),
+                MCC_TIME,
+            );
+        }
+        if v2_only {
+            return Err(Error::MissingAuthentication);
+        }
+        if let Some(authentication) = header(X_MWS_AUTHENTICATION) {
+            let credentials = authentication
+                .strip_prefix(V1_PREFIX)
+                .ok_or_else(|| invalid_header(X_MWS_AUTHENTICATION))?;
+            return Self::from_credentials(
+                1,
+                credentials,
+                X_MWS_AUTHENTICATION,
+                header(X_MWS_TIME),
+                X_MWS_TIME,
+            );
+        }
+
+        Err(Error::MissingAuthentication)
+    }
+
+    fn from_credentials(
+        version: u8,
+        credentials: &str,
+        authentication_header: &str,
+        time: Option<&str>,
+        time_header: &str,
+    ) -> Result<Self, Error> {
+        let (app_uuid, signature) = credentials
+            .split_once(':')
+            .filter(|(app_uuid, signature)| !app_uuid.is_empty() && !signature.is_empty())
+            .ok_or_else(|| invalid_header(authentication_header))?;
+        let time = time
+            .and_then(|time| time.trim().parse().ok())
+            .ok_or_else(|| invalid_header(time_header))?;
+
+        Ok(Self {
+            version,
+            app_uuid: app_uuid.to_owned(),
+            signature: signature.to_owned(),
+            time,
+        })
+    }
+
+    /// Check that the time header is within `max_time_skew` of `now`, returning
+    /// `Error::TimestampOutOfRange` if it is not.
+    pub fn check_time(&self, now: SystemTime, max_time_skew: Duration) -> Result<(), Error> {
+        match unix_time(now).abs_diff(self.time) <= max_time_skew.as_secs() {
+            true => Ok(()),
+            false => Err(Error::TimestampOutOfRange(self.time)),
+        }
+    }
+}
+
+fn invalid_header(name: &str) -> Error {
+    Error::InvalidAuthenticationHeader(name.to_owned())
+}
+
+/// Authenticates incoming HTTP requests from their MAuth headers, using an `Authenticator` to find
+/// the keys of the app each request claims to come from. This is the part shared by the server
+/// integrations, and it can be used directly by frameworks without one.
+#[derive(Debug, Clone)]
+pub struct RequestAuthenticator {
+    authenticator: Authenticator,
+    max_time_skew: Duration,
+    v2_only: bool,
+}
+
+impl RequestAuthenticator {
+    /// Initialize a request authenticator that accepts V1 and V2 signatures made within
+    /// `DEFAULT_MAX_TIME_SKEW` of the current time.
+    pub fn new(authenticator: Authenticator) -> Self {
+        Self {
+            authenticator,
+            max_time_skew: DEFAULT_MAX_TIME_SKEW,
+            v2_only: false,
+        }
+    }
+
+    /// Set the maximum difference between the MAuth time header and the current time
+    pub fn with_max_time_skew(mut self, max_time_skew: Duration) -> Self {
+        self.max_time_skew = max_time_skew;
+        self
+    }
+
+    /// Only accept V2 signatures, treating requests that only have V1 headers as unauthenticated
+    pub fn with_v2_only(mut self, v2_only: bool) -> Self {
+        self.v2_only = v2_only;
+        self
+    }
+
+    /// The authenticator used to verify signatures
+    pub fn authenticator(&self) -> &Authenticator {
+        &self.authenticator
+    }
+
+    /// Authenticate a request, looking each of its headers up by lowercase name with `header`. The
+    /// `path` must be the path of the request URI as sent, and `query` its query string without
+    /// the leading `?`.
+    ///
+    /// This verifies an RSA signature, so callers on an async executor should run it on a thread
+    /// where blocking is allowed.
+    ///
+    /// ```
+    /// # use mauth_core::authenticator::Authenticator;
+    /// # use mauth_core::http::{RequestAuthenticator, MCC_AUTHENTICATION, MCC_TIME};
+    /// # use mauth_core::keys::{KeyPair, KeySize, PrivateKeyFormat};
+    /// # use mauth_core::signer::Signer;
+    /// # use mauth_core::verifier::{RotatingVerifier, VerificationKey};
+    /// # use std::collections::HashMap;
+    /// # use std::sync::Arc;
+    /// # use std::time::{SystemTime, UNIX_EPOCH};
+    /// # let app_uuid = "101c139a-236c-11ef-b5e3-125eb8485a60";
+    /// # let signer = Signer::new(app_uuid, KeyPair::generate(KeySize::Rsa2048).unwrap().private_key_pem(PrivateKeyFormat::Pkcs1).unwrap().to_string()).unwrap();
+    /// # let mut verifier = RotatingVerifier::new(app_uuid);
+    /// # verifier.add_key(VerificationKey::new(signer.verifier())).unwrap();
+    /// # let authenticator = Authenticator::new(HashMap::from([(app_uuid.to_string(), Arc::new(verifier))]));
+    /// let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs().to_string();
+    /// let signature = signer.sign_string(2, "GET", "/item", "page=2", b"", &time).unwrap();
+    /// let authentication = format!("MWSV2 {app_uuid}:{signature};");
+    ///
+    /// let app = RequestAuthenticator::new(authenticator)
+    ///     .authenticate("GET", "/item", "page=2", b"", |name| match name {
+    ///         MCC_AUTHENTICATION => Some(authentication.as_str()),
+    ///         MCC_TIME => Some(time.as_str()),
+    ///         _ => None,
+    ///     })
+    ///     .unwrap();
+    /// assert_eq!(app.app_uuid, app_uuid);
+    /// ```
+    pub fn authenticate<'a>(
+        &self,
+        verb: &str,
+        path: &str,
+        query: &str,
+        body: &[u8],
+        header: impl Fn(&str) -> Option<&'a str>,
+    ) -> Result<AuthenticatedApp, Error> {
+        self.authenticate_at(verb, path, query, body, header, SystemTime::now())
+    }
+
+    fn authenticate_at<'a>(
+        &self,
+        verb: &str,
+        path: &str,
+        query: &str,
+        body: &[u8],
+        header: impl Fn(&str) -> Option<&'a str>,
+        now: SystemTime,
+    ) -> Result<AuthenticatedApp, Error> {
+        self.authenticate_with(header, now, |authenticator, headers| {
+            authenticator.verify_signature(
+                &headers.app_uuid,
+                headers.version,
+                verb,
+                path,
+                query,
+                body,
+                headers.time.to_string(),
+                &headers.signature,
+            )
+        })
+    }
+
+    /// Authenticate a signed response with its status code and body, looking each of its headers
+    /// up by lowercase name with `header`, in the same way as `RequestAuthenticator::authenticate`.
+    /// This lets a client check that a response came from the app it expected.
+    ///
+    /// ```
+    /// # use mauth_core::authenticator::Authenticator;
+    /// # use mauth_core::http::{sign_response, RequestAuthenticator, SigningVersions};
+    /// # use mauth_core::keys::{KeyPair, KeySize, PrivateKeyFormat};
+    /// # use mauth_core::signer::Signer;
+    /// # use std::time::{SystemTime, UNIX_EPOCH};
+    /// # let signer = Signer::new("101c139a-236c-11ef-b5e3-125eb8485a60", KeyPair::generate(KeySize::Rsa2048).unwrap().private_key_pem(PrivateKeyFormat::Pkcs1).unwrap().to_string()).unwrap();
+    /// let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    /// let headers = sign_response(&signer, SigningVersions::V2, 201, b"{}", time).unwrap();
+    ///
+    /// let app = RequestAuthenticator::new(Authenticator::from(signer.verifier()))
+    ///     .authenticate_response(201, b"{}", |name| {
+    ///         headers.iter().find(|(header, _)| *header == name).map(|(_, value)| value.as_str())
+    ///     })
+    ///     .unwrap();
+    /// assert_eq!(app.app_uuid, signer.app_uuid());
+    /// ```
+    pub fn authenticate_response<'a>(
+        &self,
+        status: u16,
+        body: &[u8],
+        header: impl Fn(&str) -> Option<&'a str>,
+    ) -> Result<AuthenticatedApp, Error> {
+        self.authenticate_with(header, SystemTime::now(), |authenticator, headers| {
+            authenticator.verify_response_signature(
+                &headers.app_uuid,
+                headers.version,
+                status,
+                body,
+                headers.time.to_string(),
+                &headers.signature,
+            )
+        })
+    }
+
+    fn authenticate_with<'a>(
+        &self,
+        header: impl Fn(&str) -> Option<&'a str>,
+        now: SystemTime,
+        verify: impl FnOnce(&Authenticator, &MAuthHeaders) -> Result<KeyFingerprint, Error>,
+    ) -> Result<AuthenticatedApp, Error> {
+        let headers = MAuthHeaders::parse(header, self.v2_only)?;
+        headers.check_time(now, self.max_time_skew)?;
+
+        let key_fingerprint = verify(&self.authenticator, &headers)?;
+
+        Ok(AuthenticatedApp {
+            app_uuid: headers.app_uuid,
+            key_fingerprint,
+            version: headers.version,
+        })
+    }
+
+    /// Authenticate a request from its `http` parts, in the same way as
+    /// `RequestAuthenticator::authenticate`.
+    pub fn authenticate_request(
+        &self,
+        parts: &http::request::Parts,
+        body: &[u8],
+    ) -> Result<AuthenticatedApp, Error> {
+        self.authenticate(
+            parts.method.as_str(),
+            parts.uri.path(),
+            parts.uri.query().unwrap_or_default(),
+            body,
+            |name| parts.headers.get(name)?.to_str().ok(),
+        )
+    }
+}
+
+/// The HTTP status to reject a request with when it could not be authenticated: `413 Payload Too
+/// Large` or `400 Bad Request` if its body could not be read, `500 Internal Server Error` if the
+/// app's keys could not be looked up, `403 Forbidden` if the app was authenticated but is not
+/// allowed on the route, and `401 Unauthorized` otherwise.
+pub fn rejection_status(error: &Error) -> http::StatusCode {
+    match error {
+        Error::BodyTooLarge(_) => http::StatusCode::PAYLOAD_TOO_LARGE,
+        Error::BodyReadError(_) => http::StatusCode::BAD_REQUEST,
+        Error::AppNotAllowed(_) => http::StatusCode::FORBIDDEN,
+        Error::IoError(_) | Error::CacheIntegrityError(_) => {
+            http::StatusCode::INTERNAL_SERVER_ERROR
+        }
+        _ => http::StatusCode::UNAUTHORIZED,
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::keys::tests::{signer, APP_UUID};
+    use crate::verifier::{RotatingVerifier, VerificationKey};
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    pub(crate) fn authenticator() -> Authenticator {
+        let mut verifier = RotatingVerifier::new(APP_UUID);
+        verifier
+            .add_key(VerificationKey::new(signer().verifier()))
+            .unwrap();
+        Authenticator::new(HashMap::from([(APP_UUID.to_owned(), Arc::new(verifier))]))
+    }
+
+    pub(crate) fn now() -> String {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            .to_string()
+    }
+
+    fn headers<'a>(pairs: &'a [(&'static str, String)]) -> impl Fn(&str) -> Option<&'a str> {
+        move |name| {
+            pairs
+                .iter()
+                .find(|(header, _)| *header == name)
+                .map(|(_, value)| value.as_str())
+        }
+    }
+
+    #[test]
+    fn parse_prefers_v2_headers() {
+        let pairs = [
+            (MCC_AUTHENTICATION, format!("MWSV2 {APP_UUID}:v2sig;")),
+            (MCC_TIME, "2".to_owned()),
+            (X_MWS_AUTHENTICATION, format!("MWS {APP_UUID}:v1sig")),
+            (X_MWS_TIME, "1".to_owned()),
+        ];
+        let parsed = MAuthHeaders::parse(headers(&pairs), false).unwrap();
+        assert_eq!((parsed.version, parsed.signature.as_str()), (2, "v2sig"));
+
+        let parsed = MAuthHeaders::parse(headers(&pairs[2..]), false).unwrap();
+        assert_eq!((parsed.version, parsed.time), (1, 1));
+        assert!(matches!(
+            MAuthHeaders::parse(headers(&pairs[2..]), true),
+            Err(Error::MissingAuthentication)
+        ));
+    }
+
+    #[test]
+    fn parse_rejects_malformed_headers() {
+        for pairs in [
+            [
+                (MCC_AUTHENTICATION, format!("MWS {APP_UUID}:sig")),
+                (MCC_TIME, "1".to_owned()),
+            ],
+            [
+                (MCC_AUTHENTICATION, "MWSV2 :sig;".to_owned()),
+                (MCC_TIME, "1".to_owned()),
+            ],
+            [
+                (MCC_AUTHENTICATION, format!("MWSV2 {APP_UUID}:sig;")),
+                (MCC_TIME, "soon".to_owned()),
+            ],
+        ] {
+            assert!(matches!(
+                MAuthHeaders::parse(headers(&pairs), false),
+                Err(Error::InvalidAuthenticationHeader(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn signed_headers_authenticate_with_each_version() {
+        let request_authenticator = RequestAuthenticator::new(authenticator());
+        let time = unix_time(SystemTime::now());
+        let pairs = sign_request(
+            &signer(),
+            SigningVersions::Both,
+            "GET",
+            "/",
+            "a=1",
+            b"",
+            time,
+        )
+        .unwrap();
+
+        let app = request_authenticator
+            .authenticate("GET", "/", "a=1", b"", headers(&pairs))
+            .unwrap();
+        assert_eq!(app.version, 2);
+        let app = request_authenticator
+            .authenticate("GET", "/", "a=1", b"", headers(&pairs[..2]))
+            .unwrap();
+        assert_eq!(app.version, 1);
+    }
+
+    #[test]
+    fn authenticate_verifies_signature_and_time() {
+        let time = now();
+        let signature = signer()
+            .sign_string(2, "POST", "/items", "a=1", b"{}", &time)
+            .unwrap();
+        let pairs = [
+            (MCC_AUTHENTICATION, format!("MWSV2 {APP_UUID}:{signature};")),
+            (MCC_TIME, time),
+        ];
+        let request_authenticator = RequestAuthenticator::new(authenticator());
+
+        let app = request_authenticator
+            .authenticate("POST", "/items", "a=1", b"{}", headers(&pairs))
+            .unwrap();
+        assert_eq!(
+            app,
+            AuthenticatedApp {
+                app_uuid: APP_UUID.to_owned(),
+                key_fingerprint: signer().fingerprint(),
+                version: 2,
+            }
+        );
+
+        assert!(matches!(
+            request_authenticator.authenticate("POST", "/items", "a=1", b"[]", headers(&pairs)),
+            Err(Error::NoMatchingKey { .. })
+        ));
+        assert!(matches!(
+            request_authenticator.authenticate_at(
+                "POST",
+                "/items",
+                "a=1",
+                b"{}",
+                headers(&pairs),
+                SystemTime::now() + Duration::from_secs(301)
+            ),
+            Err(Error::TimestampOutOfRange(_))
+        ));
+    }
+
+    #[test]
+    fn allowlist_matches_scheme_and_host() {
+        let mut allowlist = HostAllowlist::new(UnlistedHosts::Refuse);
+        allowlist.allow("HTTPS://*.Example.com").unwrap();
+        allowlist.allow("internal").unwrap();
+
+        assert!(allowlist.is_allowed(Some("https"), Some("a.b.example.com")));
+        assert!(!allowlist.is_allowed(Some("https"), Some("example.com")));
+        assert!(!allowlist.is_allowed(Some("https"), Some("badexample.com")));
+        assert!(!allowlist.is_allowed(None, Some("api.example.com")));
+        assert!(allowlist.is_allowed(None, Some("INTERNAL")));
+        assert!(!allowlist.is_allowed(Some("http"), None));
+
+        for pattern in [
+            "",
+            "://host",
+            "https://",
+            "*.",
+            "host/path",
+            "host:8080",
+            "a.*.com",
+        ] {
+            assert!(matches!(
+                allowlist.allow(pattern),
+                Err(Error::InvalidHostPattern(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,395 @@
+use crate::authenticator::KeyProvider;
+use crate::error::Error;
+use crate::keys::KeyPolicy;
+use crate::verifier::{RotatingVerifier, VerificationKey, Verifier};
+use base64::{engine::general_purpose, Engine as _};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, PoisonError, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The first line of every cache file, identifying the format version
+const CACHE_FILE_HEADER: &str = "MAUTH-KEY-CACHE 1";
+
+/// The extension of the cache files written to the directory
+const CACHE_FILE_EXTENSION: &str = "mauth-key";
+
+/// How long cached keys are used before they are fetched again, unless configured otherwise
+pub const DEFAULT_MAX_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// Caches the keys returned by an upstream `KeyProvider`, such as a client for the MAuth service,
+/// in memory and on disk, so that apps can still be authenticated after a restart while the
+/// upstream is unavailable.
+///
+/// Cached keys younger than the maximum age are used without asking the upstream. Older keys are
+/// fetched again, but are still used if the upstream fails. Keys that cannot be written to disk
+/// are logged as a warning and still used from memory.
+///
+/// Each cache file ends with a SHA-256 checksum of its contents, and files that fail the check are
+/// ignored as if they were missing. The checksum is not keyed and only detects corruption, such as
+/// a truncated write, so the directory must only be writable by the app itself.
+pub struct DiskCacheKeyProvider<P> {
+    upstream: P,
+    directory: PathBuf,
+    max_age: Duration,
+    policy: KeyPolicy,
+    memory: RwLock<HashMap<String, CachedVerifier>>,
+}
+
+#[derive(Clone)]
+struct CachedVerifier {
+    verifier: Arc<RotatingVerifier>,
+    fetched_at: SystemTime,
+}
+
+impl<P> std::fmt::Debug for DiskCacheKeyProvider<P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DiskCacheKeyProvider")
+            .field("directory", &self.directory)
+            .field("max_age", &self.max_age)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<P: KeyProvider> DiskCacheKeyProvider<P> {
+    /// Initialize a cache for the upstream provider that stores its files in `directory`, creating
+    /// the directory if needed.
+    pub fn new(upstream: P, directory: impl Into<PathBuf>) -> Result<Self, Error> {
+        let directory = directory.into();
+        std::fs::create_dir_all(&directory)?;
+
+        Ok(Self {
+            upstream,
+            directory,
+            max_age: DEFAULT_MAX_AGE,
+            policy: KeyPolicy::default(),
+            memory: RwLock::default(),
+        })
+    }
+
+    /// Change how long cached keys are used before they are fetched from the upstream again
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = max_age;
+        self
+    }
+
+    /// Check keys read back from disk against the provided `KeyPolicy` instead of the default one
+    pub fn with_policy(mut self, policy: KeyPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Read the cached keys of an app from disk. `Ok(None)` is returned if nothing is cached, and
+    /// `Error::CacheIntegrityError` if the file has been corrupted.
+    pub fn read_cached(&self, app_uuid: &str) -> Result<Option<Arc<RotatingVerifier>>, Error> {
+        Ok(self.read_file(app_uuid)?.map(|cached| cached.verifier))
+    }
+
+    fn cached(&self, app_uuid: &str) -> Option<CachedVerifier> {
+        let memory = self.memory.read().unwrap_or_else(PoisonError::into_inner);
+        if let Some(cached) = memory.get(app_uuid) {
+            return Some(cached.clone());
+        }
+        drop(memory);
+
+        let cached = self.read_file(app_uuid).ok().flatten()?;
+        self.remember(app_uuid, cached.clone());
+        Some(cached)
+    }
+
+    fn remember(&self, app_uuid: &str, cached: CachedVerifier) {
+        self.memory
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(app_uuid.to_owned(), cached);
+    }
+
+    fn path(&self, app_uuid: &str) -> PathBuf {
+        self.directory
+            .join(format!("{app_uuid}.{CACHE_FILE_EXTENSION}"))
+    }
+
+    fn read_file(&self, app_uuid: &str) -> Result<Option<CachedVerifier>, Error> {
+        let path = self.path(app_uuid);
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        parse_cache_file(app_uuid, &contents, &self.policy)
+            .map(Some)
+            .ok_or(Error::CacheIntegrityError(path))
+    }
+
+    fn write_file(&self, verifier: &RotatingVerifier, fetched_at: SystemTime) -> Result<(), Error> {
+        let contents = format_cache_file(verifier, fetched_at)?;
+        // Each write gets its own temporary file, so that concurrent writes for the same app
+        // cannot rename each other's partly written files into place
+        let mut file = tempfile::NamedTempFile::new_in(&self.directory)?;
+        file.write_all(contents.as_bytes())?;
+        file.persist(self.path(verifier.app_uuid()))
+            .map_err(|e| e.error)?;
+
+        Ok(())
+    }
+}
+
+impl<P: KeyProvider> KeyProvider for DiskCacheKeyProvider<P> {
+    fn verifier(&self, app_uuid: &str) -> Result<Option<Arc<RotatingVerifier>>, Error> {
+        // The app UUID comes from the request, so never let it pick a path outside the directory
+        if !is_safe_file_name(app_uuid) {
+            return self.upstream.verifier(app_uuid);
+        }
+
+        let now = SystemTime::now();
+        let cached = self.cached(app_uuid);
+        if let Some(cached) = &cached {
+            let age = now.duration_since(cached.fetched_at).unwrap_or_default();
+            if age < self.max_age {
+                return Ok(Some(cached.verifier.clone()));
+            }
+        }
+
+        match self.upstream.verifier(app_uuid) {
+            Ok(Some(verifier)) => {
+                // A failure to persist the keys must not fail the request that fetched them
+                if let Err(e) = self.write_file(&verifier, now) {
+                    log::warn!(
+                        "Unable to cache MAuth keys for app {app_uuid} in {}: {e}",
+                        self.directory.display()
+                    );
+                }
+                self.remember(
+                    app_uuid,
+                    CachedVerifier {
+                        verifier: verifier.clone(),
+                        fetched_at: now,
+                    },
+                );
+                Ok(Some(verifier))
+            }
+            Ok(None) => Ok(None),
+            Err(e) => cached.map(|cached| Some(cached.verifier)).ok_or(e),
+        }
+    }
+}
+
+fn is_safe_file_name(app_uuid: &str) -> bool {
+    !app_uuid.is_empty()
+        && app_uuid
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+}
+
+fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn format_time(time: Option<SystemTime>) -> String {
+    time.map_or_else(|| "-".to_owned(), |time| unix_seconds(time).to_string())
+}
+
+fn parse_time(value: &str) -> Option<Option<SystemTime>> {
+    match value {
+        "-" => Some(None),
+        seconds => Some(Some(
+            UNIX_EPOCH.checked_add(Duration::from_secs(seconds.parse().ok()?))?,
+        )),
+    }
+}
+
+fn format_cache_file(verifier: &RotatingVerifier, fetched_at: SystemTime) -> Result<String, Error> {
+    let mut contents = format!(
+        "{CACHE_FILE_HEADER}\napp_uuid {}\nfetched_at {}\n",
+        verifier.app_uuid(),
+        unix_seconds(fetched_at)
+    );
+    for key in verifier.keys() {
+        contents.push_str(&format!(
+            "key {} {} {}\n",
+            format_time(key.not_before()),
+            format_time(key.not_after()),
+            general_purpose::STANDARD.encode(key.verifier().public_key_der()?)
+        ));
+    }
+    let digest = hex::encode(Sha256::digest(contents.as_bytes()));
+    contents.push_str(&format!("sha256 {digest}\n"));
+
+    Ok(contents)
+}
+
+fn parse_cache_file(app_uuid: &str, contents: &str, policy: &KeyPolicy) -> Option<CachedVerifier> {
+    let body_end = contents.trim_end_matches('\n').rfind('\n')? + 1;
+    let (body, digest_line) = contents.split_at(body_end);
+    let digest = digest_line.trim_end().strip_prefix("sha256 ")?;
+    if hex::encode(Sha256::digest(body.as_bytes())) != digest {
+        return None;
+    }
+
+    let mut lines = body.lines();
+    if lines.next()? != CACHE_FILE_HEADER || lines.next()?.strip_prefix("app_uuid ")? != app_uuid {
+        return None;
+    }
+    let fetched_at = parse_time(lines.next()?.strip_prefix("fetched_at ")?)??;
+
+    let mut verifier = RotatingVerifier::new(app_uuid);
+    for line in lines {
+        let mut fields = line.strip_prefix("key ")?.split(' ');
+        let not_before = parse_time(fields.next()?)?;
+        let not_after = parse_time(fields.next()?)?;
+        let der = general_purpose::STANDARD.decode(fields.next()?).ok()?;
+
+        let mut key =
+            VerificationKey::new(Verifier::from_public_key_der(app_uuid, &der, policy).ok()?);
+        if let Some(not_before) = not_before {
+            key = key.with_not_before(not_before);
+        }
+        if let Some(not_after) = not_after {
+            key = key.with_not_after(not_after);
+        }
+        verifier.add_key(key).ok()?;
+    }
+
+    Some(CachedVerifier {
+        verifier: Arc::new(verifier),
+        fetched_at,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::tests::{signer, APP_UUID};
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    #[derive(Default)]
+    struct Upstream {
+        calls: AtomicUsize,
+        unavailable: AtomicBool,
+    }
+
+    impl KeyProvider for Upstream {
+        fn verifier(&self, app_uuid: &str) -> Result<Option<Arc<RotatingVerifier>>, Error> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if self.unavailable.load(Ordering::SeqCst) {
+                return Err(Error::IoError(std::io::ErrorKind::ConnectionRefused.into()));
+            }
+            if app_uuid != APP_UUID {
+                return Ok(None);
+            }
+
+            let mut verifier = RotatingVerifier::new(APP_UUID);
+            verifier
+                .add_key(
+                    VerificationKey::new(signer().verifier())
+                        .with_not_after(UNIX_EPOCH + Duration::from_secs(4_102_444_800)),
+                )
+                .unwrap();
+            Ok(Some(Arc::new(verifier)))
+        }
+    }
+
+    #[test]
+    fn keys_survive_a_restart_during_an_upstream_outage() {
+        let directory = tempfile::tempdir().unwrap();
+        let upstream = Arc::new(Upstream::default());
+        let cache = DiskCacheKeyProvider::new(upstream.clone(), directory.path()).unwrap();
+        assert!(cache.verifier(APP_UUID).unwrap().is_some());
+        assert!(cache.verifier(APP_UUID).unwrap().is_some());
+        assert_eq!(upstream.calls.load(Ordering::SeqCst), 1);
+
+        upstream.unavailable.store(true, Ordering::SeqCst);
+        let restarted = DiskCacheKeyProvider::new(upstream.clone(), directory.path())
+            .unwrap()
+            .with_max_age(Duration::ZERO);
+        let verifier = restarted.verifier(APP_UUID).unwrap().unwrap();
+
+        assert_eq!(upstream.calls.load(Ordering::SeqCst), 2);
+        assert_eq!(
+            verifier.keys()[0].verifier().fingerprint(),
+            signer().fingerprint()
+        );
+        assert_eq!(
+            verifier.keys()[0].not_after(),
+            Some(UNIX_EPOCH + Duration::from_secs(4_102_444_800))
+        );
+        assert!(matches!(
+            restarted.verifier("other-app"),
+            Err(Error::IoError(_))
+        ));
+    }
+
+    #[test]
+    fn corrupted_cache_files_are_rejected() {
+        let directory = tempfile::tempdir().unwrap();
+        let upstream = Arc::new(Upstream::default());
+        let cache = DiskCacheKeyProvider::new(upstream.clone(), directory.path()).unwrap();
+        cache.verifier(APP_UUID).unwrap();
+
+        let path = cache.path(APP_UUID);
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::write(&path, contents.replacen("fetched_at ", "fetched_at 9", 1)).unwrap();
+
+        assert!(matches!(
+            cache.read_cached(APP_UUID),
+            Err(Error::CacheIntegrityError(_))
+        ));
+
+        upstream.unavailable.store(true, Ordering::SeqCst);
+        let restarted = DiskCacheKeyProvider::new(upstream, directory.path()).unwrap();
+        assert!(restarted.verifier(APP_UUID).is_err());
+    }
+
+    #[test]
+    fn times_that_cannot_be_represented_are_corrupt() {
+        assert_eq!(parse_time("-"), Some(None));
+        assert!(parse_time("4102444800").is_some_and(|time| time.is_some()));
+        assert_eq!(parse_time(&u64::MAX.to_string()), None);
+    }
+
+    #[test]
+    fn concurrent_writes_leave_a_complete_cache_file() {
+        let directory = tempfile::tempdir().unwrap();
+        let upstream = Upstream::default();
+        let verifier = upstream.verifier(APP_UUID).unwrap().unwrap();
+        let cache = DiskCacheKeyProvider::new(upstream, directory.path()).unwrap();
+
+        std::thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    for _ in 0..20 {
+                        cache.write_file(&verifier, SystemTime::now()).unwrap();
+                    }
+                });
+            }
+        });
+
+        assert!(cache.read_cached(APP_UUID).unwrap().is_some());
+        assert_eq!(std::fs::read_dir(directory.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn keys_are_used_when_they_cannot_be_cached() {
+        let directory = tempfile::tempdir().unwrap();
+        let cache =
+            DiskCacheKeyProvider::new(Arc::new(Upstream::default()), directory.path()).unwrap();
+        std::fs::remove_dir(directory.path()).unwrap();
+
+        assert!(cache.verifier(APP_UUID).unwrap().is_some());
+    }
+
+    #[test]
+    fn unsafe_app_uuids_never_touch_the_disk() {
+        let directory = tempfile::tempdir().unwrap();
+        let cache =
+            DiskCacheKeyProvider::new(Arc::new(Upstream::default()), directory.path()).unwrap();
+
+        assert!(cache.verifier("../escape").unwrap().is_none());
+        assert_eq!(std::fs::read_dir(directory.path()).unwrap().count(), 0);
+    }
+}
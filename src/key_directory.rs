@@ -0,0 +1,329 @@
+use crate::authenticator::KeyProvider;
+use crate::error::Error;
+use crate::keys::KeyPolicy;
+use crate::verifier::{RotatingVerifier, VerificationKey, Verifier};
+use notify::event::ModifyKind;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+#[cfg(test)]
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+use std::time::Duration;
+
+/// The extension of the public key files read from the directory
+const PUBLIC_KEY_EXTENSION: &str = "pub";
+
+/// How long the directory has to stay unchanged before a watched directory is reloaded, so that a
+/// burst of changes, such as a Kubernetes secret being updated, causes a single reload
+const RELOAD_DELAY: Duration = Duration::from_millis(100);
+
+type Verifiers = HashMap<String, Arc<RotatingVerifier>>;
+
+/// Loads the public keys of other apps from a directory of `{app_uuid}.pub` PEM files, such as a
+/// mounted Kubernetes secret, and optionally watches the directory to reload them when it changes.
+///
+/// Every reload reads the whole directory and then swaps the new set of keys in at once. A file that
+/// cannot be read or parsed is logged and skipped, and if the app had a good key before, that key
+/// keeps being used until the file is fixed. Removing a file removes the app.
+#[derive(Debug)]
+pub struct DirectoryKeyProvider {
+    inner: Arc<Inner>,
+    watcher: Option<RecommendedWatcher>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    directory: PathBuf,
+    policy: KeyPolicy,
+    verifiers: RwLock<Arc<Verifiers>>,
+    /// Held for the whole of a reload, so that a reload from the watcher and a manual one cannot
+    /// both start from the same previous keys and have the slower one swap in stale keys
+    reloading: Mutex<()>,
+    #[cfg(test)]
+    reloads: AtomicUsize,
+}
+
+impl DirectoryKeyProvider {
+    /// Load the keys in a directory. Keys must meet the default `KeyPolicy`. An error is only
+    /// returned if the directory itself cannot be read.
+    ///
+    /// ```
+    /// # use mauth_core::authenticator::KeyProvider;
+    /// # use mauth_core::key_directory::DirectoryKeyProvider;
+    /// # use mauth_core::keys::{KeyPair, KeySize, PublicKeyFormat};
+    /// # let public_key = KeyPair::generate(KeySize::Rsa2048).unwrap().public_key_pem(PublicKeyFormat::Spki).unwrap();
+    /// let directory = tempfile::tempdir().unwrap();
+    /// std::fs::write(directory.path().join("101c139a-236c-11ef-b5e3-125eb8485a60.pub"), public_key).unwrap();
+    ///
+    /// let provider = DirectoryKeyProvider::load(directory.path()).unwrap().watch().unwrap();
+    /// assert!(provider.verifier("101c139a-236c-11ef-b5e3-125eb8485a60").unwrap().is_some());
+    /// ```
+    pub fn load(directory: impl Into<PathBuf>) -> Result<Self, Error> {
+        Self::load_with_policy(directory, KeyPolicy::default())
+    }
+
+    /// Load the keys in a directory in the same way as `DirectoryKeyProvider::load`, checking keys
+    /// against the provided `KeyPolicy` instead of the default one.
+    pub fn load_with_policy(
+        directory: impl Into<PathBuf>,
+        policy: KeyPolicy,
+    ) -> Result<Self, Error> {
+        let inner = Arc::new(Inner {
+            directory: directory.into(),
+            policy,
+            verifiers: RwLock::default(),
+            reloading: Mutex::default(),
+            #[cfg(test)]
+            reloads: AtomicUsize::default(),
+        });
+        inner.reload()?;
+
+        Ok(Self {
+            inner,
+            watcher: None,
+        })
+    }
+
+    /// Watch the directory and reload the keys whenever files are created, written, renamed or
+    /// removed in it, using inotify on Linux and the native file watching mechanism on other
+    /// platforms. Changes are reloaded on a background thread once the directory has been unchanged
+    /// for 100ms. Watching stops when the provider is dropped.
+    pub fn watch(mut self) -> Result<Self, Error> {
+        let (changes, changed) = mpsc::channel();
+        let directory = self.inner.directory.clone();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| match event {
+                Ok(event) if changes_keys(&event.kind) => {
+                    // The receiver is only dropped once the reload thread has stopped
+                    let _ = changes.send(());
+                }
+                Ok(_) => {}
+                Err(e) => log::error!("Unable to watch {} for changes: {e}", directory.display()),
+            })
+            .map_err(std::io::Error::other)?;
+        watcher
+            .watch(&self.inner.directory, RecursiveMode::NonRecursive)
+            .map_err(std::io::Error::other)?;
+
+        let inner = self.inner.clone();
+        std::thread::Builder::new()
+            .name("mauth-key-directory".to_owned())
+            .spawn(move || inner.reload_on_changes(changed))?;
+        self.watcher = Some(watcher);
+
+        Ok(self)
+    }
+
+    /// Read the directory again and swap in the new set of keys. Invalid files are logged and keep
+    /// their previous good key, if there was one.
+    pub fn reload(&self) -> Result<(), Error> {
+        self.inner.reload()
+    }
+
+    /// The directory the keys are loaded from
+    pub fn directory(&self) -> &Path {
+        &self.inner.directory
+    }
+
+    /// The UUIDs of all of the apps with a loaded key
+    pub fn app_uuids(&self) -> Vec<String> {
+        self.inner.current().keys().cloned().collect()
+    }
+}
+
+impl KeyProvider for DirectoryKeyProvider {
+    fn verifier(&self, app_uuid: &str) -> Result<Option<Arc<RotatingVerifier>>, Error> {
+        Ok(self.inner.current().get(app_uuid).cloned())
+    }
+}
+
+impl Inner {
+    fn current(&self) -> Arc<Verifiers> {
+        self.verifiers
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Reload the keys after each burst of changes, until the watcher sending them is dropped
+    fn reload_on_changes(&self, changed: Receiver<()>) {
+        while changed.recv().is_ok() {
+            while changed.recv_timeout(RELOAD_DELAY).is_ok() {}
+            if let Err(e) = self.reload() {
+                log::error!(
+                    "Unable to reload MAuth keys from {}: {e}",
+                    self.directory.display()
+                );
+            }
+        }
+    }
+
+    fn reload(&self) -> Result<(), Error> {
+        let _reloading = self
+            .reloading
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        #[cfg(test)]
+        self.reloads.fetch_add(1, Ordering::Relaxed);
+        let previous = self.current();
+        let mut verifiers = Verifiers::new();
+
+        for entry in std::fs::read_dir(&self.directory)? {
+            let path = entry?.path();
+            let Some(app_uuid) = app_uuid(&path) else {
+                continue;
+            };
+
+            match self.load_key(&app_uuid, &path) {
+                Ok(verifier) => {
+                    verifiers.insert(app_uuid, Arc::new(verifier));
+                }
+                Err(e) => {
+                    log::error!("Rejected MAuth public key {}: {e}", path.display());
+                    if let Some(verifier) = previous.get(&app_uuid) {
+                        verifiers.insert(app_uuid, verifier.clone());
+                    }
+                }
+            }
+        }
+
+        *self
+            .verifiers
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = Arc::new(verifiers);
+
+        Ok(())
+    }
+
+    fn load_key(&self, app_uuid: &str, path: &Path) -> Result<RotatingVerifier, Error> {
+        let public_key = std::fs::read_to_string(path)?;
+        let mut verifier = RotatingVerifier::new(app_uuid);
+        verifier.add_key(VerificationKey::new(Verifier::new_with_policy(
+            app_uuid,
+            public_key,
+            &self.policy,
+        )?))?;
+
+        Ok(verifier)
+    }
+}
+
+/// Whether a watched event may have changed the keys. The events for files being opened and read,
+/// which each reload causes itself, and for metadata changes are ignored.
+fn changes_keys(kind: &EventKind) -> bool {
+    matches!(
+        kind,
+        EventKind::Create(_)
+            | EventKind::Remove(_)
+            | EventKind::Modify(ModifyKind::Data(_) | ModifyKind::Name(_) | ModifyKind::Any)
+    )
+}
+
+/// The app UUID a file holds the key of, or `None` if it is not a public key file. Hidden files
+/// are skipped, as Kubernetes uses them for the versioned copies behind a mounted secret.
+fn app_uuid(path: &Path) -> Option<String> {
+    if path.extension()? != PUBLIC_KEY_EXTENSION {
+        return None;
+    }
+    let app_uuid = path.file_stem()?.to_str()?;
+    match app_uuid.is_empty() || app_uuid.starts_with('.') {
+        true => None,
+        false => Some(app_uuid.to_owned()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::tests::{key_pair, signer, APP_UUID};
+    use crate::keys::PublicKeyFormat;
+    use std::time::{Duration, Instant};
+
+    fn write_key(directory: &Path, app_uuid: &str) {
+        let public_key = key_pair().public_key_pem(PublicKeyFormat::Spki).unwrap();
+        std::fs::write(directory.join(format!("{app_uuid}.pub")), public_key).unwrap();
+    }
+
+    #[test]
+    fn load_reads_public_key_files_and_skips_bad_ones() {
+        let directory = tempfile::tempdir().unwrap();
+        write_key(directory.path(), APP_UUID);
+        std::fs::write(directory.path().join("bad.pub"), "not a key").unwrap();
+        std::fs::write(directory.path().join("README"), "ignored").unwrap();
+
+        let provider = DirectoryKeyProvider::load(directory.path()).unwrap();
+
+        assert_eq!(provider.app_uuids(), [APP_UUID]);
+        let verifier = provider.verifier(APP_UUID).unwrap().unwrap();
+        assert_eq!(
+            verifier.keys()[0].verifier().fingerprint(),
+            signer().fingerprint()
+        );
+    }
+
+    #[test]
+    fn reload_keeps_previous_key_when_file_becomes_invalid() {
+        let directory = tempfile::tempdir().unwrap();
+        write_key(directory.path(), APP_UUID);
+        write_key(directory.path(), "removed-app");
+        let provider = DirectoryKeyProvider::load(directory.path()).unwrap();
+
+        std::fs::write(directory.path().join(format!("{APP_UUID}.pub")), "garbage").unwrap();
+        std::fs::remove_file(directory.path().join("removed-app.pub")).unwrap();
+        provider.reload().unwrap();
+
+        assert!(provider.verifier(APP_UUID).unwrap().is_some());
+        assert!(provider.verifier("removed-app").unwrap().is_none());
+    }
+
+    #[test]
+    fn watch_reloads_when_directory_changes() {
+        let directory = tempfile::tempdir().unwrap();
+        let provider = DirectoryKeyProvider::load(directory.path())
+            .unwrap()
+            .watch()
+            .unwrap();
+        assert!(provider.verifier(APP_UUID).unwrap().is_none());
+
+        write_key(directory.path(), APP_UUID);
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while provider.verifier(APP_UUID).unwrap().is_none() {
+            assert!(Instant::now() < deadline, "key was not reloaded");
+            std::thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    #[test]
+    fn watch_reloads_once_after_a_change() {
+        let directory = tempfile::tempdir().unwrap();
+        let provider = DirectoryKeyProvider::load(directory.path())
+            .unwrap()
+            .watch()
+            .unwrap();
+
+        write_key(directory.path(), APP_UUID);
+        std::fs::write(directory.path().join("bad.pub"), "not a key").unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while provider.verifier(APP_UUID).unwrap().is_none() {
+            assert!(Instant::now() < deadline, "key was not reloaded");
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        std::thread::sleep(RELOAD_DELAY * 5);
+        let reloads = provider.inner.reloads.load(Ordering::Relaxed);
+        assert!(reloads <= 3, "reloaded {reloads} times");
+        std::thread::sleep(RELOAD_DELAY * 5);
+        assert_eq!(provider.inner.reloads.load(Ordering::Relaxed), reloads);
+    }
+
+    #[test]
+    fn load_fails_for_missing_directory() {
+        assert!(matches!(
+            DirectoryKeyProvider::load("/nonexistent/mauth/keys"),
+            Err(Error::IoError(_))
+        ));
+    }
+}
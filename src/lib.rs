@@ -1,12 +1,59 @@
 #![forbid(unsafe_code)]
 #![doc = include_str!("../README.md")]
 
+/// actix-web middleware that authenticates MAuth requests
+#[cfg(feature = "actix")]
+pub mod actix;
+/// Verification of requests from any of several apps
+pub mod authenticator;
+/// Extractors for the app authenticated by MAuth in axum handlers
+#[cfg(feature = "axum")]
+pub mod axum_extract;
+/// Detached signatures for files and artifacts
+pub mod detached;
 /// Error types
 pub mod error;
+/// gRPC layers that sign and authenticate tonic calls
+#[cfg(feature = "tonic")]
+pub mod grpc;
+/// Authentication of MAuth signed HTTP requests
+#[cfg(feature = "http")]
+pub mod http;
+/// Disk cache for the keys returned by another key provider
+pub mod key_cache;
+/// Public keys loaded from a watched directory of PEM files
+#[cfg(feature = "key-directory")]
+pub mod key_directory;
+/// Key pair generation and export
+pub mod keys;
+/// Signed envelopes for messages published to queues and event streams
+pub mod message;
+/// Per-route enforcement of authentication for the server integrations
+#[cfg(feature = "http")]
+pub mod policy;
+/// Selection between the signers of a service that acts as several apps
+pub mod registry;
+/// reqwest middleware that signs outgoing requests
+#[cfg(feature = "reqwest")]
+pub mod reqwest_client;
+/// Revocation of compromised apps and keys
+pub mod revocation;
 pub(crate) mod signable;
 /// Signing for outgoing requests
 pub mod signer;
+/// Tower middleware for clients that sign outgoing MAuth requests
+#[cfg(feature = "tower")]
+pub mod tower_client;
+/// Tower middleware for servers that authenticate MAuth requests
+#[cfg(feature = "tower")]
+pub mod tower_server;
+/// Static app-to-key mappings loaded from a file
+#[cfg(feature = "trust-store")]
+pub mod trust_store;
 /// Signature verification for incoming requests
 pub mod verifier;
+/// Signing and authentication of tungstenite WebSocket handshakes
+#[cfg(feature = "tungstenite")]
+pub mod websocket;
 
 mod pem_format;
@@ -0,0 +1,374 @@
+use crate::authenticator::Authenticator;
+use crate::error::Error;
+use crate::keys::KeyFingerprint;
+use crate::signer::Signer;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The message header holding `MWSMSG {app_uuid}:{signature}`
+pub const MESSAGE_AUTHENTICATION: &str = "mcc-message-authentication";
+/// The message header holding the Unix time the message was signed at
+pub const MESSAGE_TIME: &str = "mcc-message-time";
+
+/// The default maximum age of a consumed message
+pub const DEFAULT_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The default maximum time a message can have been signed at ahead of the current time
+pub const DEFAULT_MAX_CLOCK_SKEW: Duration = Duration::from_secs(300);
+
+const PREFIX: &str = "MWSMSG ";
+
+/// The MAuth headers of a message published to a queue or event stream, such as Kafka record
+/// headers or AMQP message properties, which let consumers check which app produced it.
+///
+/// The signature covers the topic, a digest of the payload, the app UUID and the time, as described
+/// in `Signer::sign_message`. The topic is not sent in the headers, as consumers already know it.
+///
+/// ```
+/// # use mauth_core::keys::{KeyPair, KeySize, PrivateKeyFormat};
+/// # use mauth_core::message::{MessageHeaders, MESSAGE_AUTHENTICATION};
+/// # use mauth_core::signer::Signer;
+/// # let signer = Signer::new("101c139a-236c-11ef-b5e3-125eb8485a60", KeyPair::generate(KeySize::Rsa2048).unwrap().private_key_pem(PrivateKeyFormat::Pkcs1).unwrap().to_string()).unwrap();
+/// let headers = MessageHeaders::sign(&signer, "orders.created", b"{}", 1669858655).unwrap();
+/// let serialized = headers.to_headers();
+///
+/// let parsed = MessageHeaders::parse(|name| {
+///     serialized.iter().find(|(header, _)| *header == name).map(|(_, value)| value.as_str())
+/// });
+/// assert_eq!(parsed.unwrap(), headers);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageHeaders {
+    /// The UUID of the app that signed the message
+    pub app_uuid: String,
+    /// The Base64 encoded signature
+    pub signature: String,
+    /// The Unix time the message was signed at
+    pub time: u64,
+}
+
+impl MessageHeaders {
+    /// Sign a message published to `topic` at the Unix time `time`
+    pub fn sign(signer: &Signer, topic: &str, payload: &[u8], time: u64) -> Result<Self, Error> {
+        let signature = signer.sign_message(topic, payload, time.to_string())?;
+
+        Ok(Self {
+            app_uuid: signer.app_uuid().to_owned(),
+            signature,
+            time,
+        })
+    }
+
+    /// Parse the MAuth headers of a message, looking each header up by its name with `header`.
+    /// `Error::MissingAuthentication` is returned if there are none.
+    pub fn parse<'a>(header: impl Fn(&str) -> Option<&'a str>) -> Result<Self, Error> {
+        let invalid = |name: &str| Error::InvalidAuthenticationHeader(name.to_owned());
+        let authentication = header(MESSAGE_AUTHENTICATION).ok_or(Error::MissingAuthentication)?;
+        let (app_uuid, signature) = authentication
+            .strip_prefix(PREFIX)
+            .and_then(|credentials| credentials.split_once(':'))
+            .filter(|(app_uuid, signature)| !app_uuid.is_empty() && !signature.is_empty())
+            .ok_or_else(|| invalid(MESSAGE_AUTHENTICATION))?;
+        let time = header(MESSAGE_TIME)
+            .and_then(|time| time.parse().ok())
+            .ok_or_else(|| invalid(MESSAGE_TIME))?;
+
+        Ok(Self {
+            app_uuid: app_uuid.to_owned(),
+            signature: signature.to_owned(),
+            time,
+        })
+    }
+
+    /// The headers to attach to the message
+    pub fn to_headers(&self) -> Vec<(&'static str, String)> {
+        vec![
+            (
+                MESSAGE_AUTHENTICATION,
+                format!("{PREFIX}{}:{}", self.app_uuid, self.signature),
+            ),
+            (MESSAGE_TIME, self.time.to_string()),
+        ]
+    }
+}
+
+/// The app that produced a message, as authenticated by a `MessageAuthenticator`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AuthenticatedMessage {
+    /// The UUID of the app that signed the message
+    pub app_uuid: String,
+    /// The fingerprint of the key the signature was verified with
+    pub key_fingerprint: KeyFingerprint,
+    /// The Unix time the message was signed at
+    pub time: u64,
+}
+
+/// Authenticates consumed messages from their MAuth headers, looking the keys of the apps that
+/// produced them up in an `Authenticator`.
+///
+/// Messages may be consumed long after they were published, so they are verified with the keys
+/// that were valid at the time they were signed, and messages published before a key rotation can
+/// still be consumed. As the signing time is chosen by the producer, messages older than a maximum
+/// age, `DEFAULT_MAX_AGE` unless set otherwise, or signed more than a maximum clock skew ahead of
+/// the current time fail with `Error::TimestampOutOfRange`. This stops a retired key from signing
+/// messages backdated into its validity period for longer than the maximum age.
+#[derive(Debug, Clone)]
+pub struct MessageAuthenticator {
+    authenticator: Authenticator,
+    max_age: Duration,
+    max_clock_skew: Duration,
+}
+
+impl MessageAuthenticator {
+    /// Initialize a message authenticator that looks app keys up in `authenticator` and accepts
+    /// messages signed within `DEFAULT_MAX_AGE` before, or `DEFAULT_MAX_CLOCK_SKEW` after, the
+    /// current time.
+    pub fn new(authenticator: Authenticator) -> Self {
+        Self {
+            authenticator,
+            max_age: DEFAULT_MAX_AGE,
+            max_clock_skew: DEFAULT_MAX_CLOCK_SKEW,
+        }
+    }
+
+    /// Set the maximum time between a message being signed and the current time. This should
+    /// cover the longest a message may wait to be consumed, and keys should be trusted for at
+    /// least this long after being retired.
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = max_age;
+        self
+    }
+
+    /// Set the maximum time a message can have been signed at ahead of the current time
+    pub fn with_max_clock_skew(mut self, max_clock_skew: Duration) -> Self {
+        self.max_clock_skew = max_clock_skew;
+        self
+    }
+
+    /// The authenticator that app keys are looked up in
+    pub fn authenticator(&self) -> &Authenticator {
+        &self.authenticator
+    }
+
+    /// Authenticate a message consumed from `topic`, looking each of its headers up by name with
+    /// `header`.
+    ///
+    /// ```
+    /// # use mauth_core::authenticator::Authenticator;
+    /// # use mauth_core::keys::{KeyPair, KeySize, PrivateKeyFormat};
+    /// # use mauth_core::message::{MessageAuthenticator, MessageHeaders};
+    /// # use mauth_core::signer::Signer;
+    /// # use std::time::{SystemTime, UNIX_EPOCH};
+    /// # let signer = Signer::new("101c139a-236c-11ef-b5e3-125eb8485a60", KeyPair::generate(KeySize::Rsa2048).unwrap().private_key_pem(PrivateKeyFormat::Pkcs1).unwrap().to_string()).unwrap();
+    /// let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+    /// let headers = MessageHeaders::sign(&signer, "orders.created", b"{}", now).unwrap().to_headers();
+    ///
+    /// let message = MessageAuthenticator::new(Authenticator::from(signer.verifier()))
+    ///     .authenticate("orders.created", b"{}", |name| {
+    ///         headers.iter().find(|(header, _)| *header == name).map(|(_, value)| value.as_str())
+    ///     })
+    ///     .unwrap();
+    /// assert_eq!(message.app_uuid, signer.app_uuid());
+    /// ```
+    pub fn authenticate<'a>(
+        &self,
+        topic: &str,
+        payload: &[u8],
+        header: impl Fn(&str) -> Option<&'a str>,
+    ) -> Result<AuthenticatedMessage, Error> {
+        self.authenticate_at(topic, payload, header, SystemTime::now())
+    }
+
+    fn authenticate_at<'a>(
+        &self,
+        topic: &str,
+        payload: &[u8],
+        header: impl Fn(&str) -> Option<&'a str>,
+        now: SystemTime,
+    ) -> Result<AuthenticatedMessage, Error> {
+        let headers = MessageHeaders::parse(header)?;
+        let signed_at = UNIX_EPOCH
+            .checked_add(Duration::from_secs(headers.time))
+            .ok_or_else(|| {
+                Error::InvalidAuthenticationHeader(format!(
+                    "message time {} is not a valid Unix time",
+                    headers.time
+                ))
+            })?;
+        let in_range = match now.duration_since(signed_at) {
+            Ok(age) => age <= self.max_age,
+            Err(e) => e.duration() <= self.max_clock_skew,
+        };
+        if !in_range {
+            return Err(Error::TimestampOutOfRange(headers.time));
+        }
+
+        let key_fingerprint = self.authenticator.verify_message_signature(
+            &headers.app_uuid,
+            topic,
+            payload,
+            headers.time.to_string(),
+            headers.signature,
+        )?;
+
+        Ok(AuthenticatedMessage {
+            app_uuid: headers.app_uuid,
+            key_fingerprint,
+            time: headers.time,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::tests::{next_signer, signer, APP_UUID};
+    use crate::verifier::{RotatingVerifier, VerificationKey};
+    use rstest::rstest;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    const TIME: u64 = 1669858655;
+
+    fn signed_at(time: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(time)
+    }
+
+    fn authenticator() -> MessageAuthenticator {
+        MessageAuthenticator::new(Authenticator::from(signer().verifier()))
+    }
+
+    fn header<'a>(headers: &'a [(&'static str, String)]) -> impl Fn(&str) -> Option<&'a str> {
+        move |name: &str| {
+            headers
+                .iter()
+                .find(|(header, _)| *header == name)
+                .map(|(_, value)| value.as_str())
+        }
+    }
+
+    #[test]
+    fn messages_authenticate_only_on_their_topic() {
+        let headers = MessageHeaders::sign(&signer(), "orders", b"{}", TIME)
+            .unwrap()
+            .to_headers();
+        let authenticate = |topic, payload| {
+            authenticator().authenticate_at(topic, payload, header(&headers), signed_at(TIME))
+        };
+
+        let message = authenticate("orders", b"{}").unwrap();
+        assert_eq!((message.app_uuid.as_str(), message.time), (APP_UUID, TIME));
+
+        assert!(authenticate("payments", b"{}").is_err());
+        assert!(authenticate("orders", b"[]").is_err());
+    }
+
+    #[test]
+    fn messages_verify_with_the_key_valid_when_they_were_signed() {
+        let rotated_at = signed_at(TIME + 60);
+        let mut verifier = RotatingVerifier::new(APP_UUID);
+        verifier
+            .add_key(VerificationKey::new(signer().verifier()).with_not_after(rotated_at))
+            .unwrap();
+        verifier
+            .add_key(VerificationKey::new(next_signer().verifier()).with_not_before(rotated_at))
+            .unwrap();
+        let authenticator = MessageAuthenticator::new(Authenticator::new(HashMap::from([(
+            APP_UUID.to_owned(),
+            Arc::new(verifier),
+        )])));
+        let now = signed_at(TIME + 120);
+
+        let before = MessageHeaders::sign(&signer(), "orders", b"{}", TIME)
+            .unwrap()
+            .to_headers();
+        let message = authenticator
+            .authenticate_at("orders", b"{}", header(&before), now)
+            .unwrap();
+        assert_eq!(message.key_fingerprint, signer().fingerprint());
+
+        let after = MessageHeaders::sign(&signer(), "orders", b"{}", TIME + 120)
+            .unwrap()
+            .to_headers();
+        assert!(matches!(
+            authenticator.authenticate_at("orders", b"{}", header(&after), now),
+            Err(Error::NoMatchingKey { .. })
+        ));
+        let after = MessageHeaders::sign(&next_signer(), "orders", b"{}", TIME + 120)
+            .unwrap()
+            .to_headers();
+        let message = authenticator
+            .authenticate_at("orders", b"{}", header(&after), now)
+            .unwrap();
+        assert_eq!(message.key_fingerprint, next_signer().fingerprint());
+    }
+
+    #[rstest]
+    #[case(authenticator(), TIME + DEFAULT_MAX_AGE.as_secs(), true)]
+    #[case(authenticator(), TIME + DEFAULT_MAX_AGE.as_secs() + 1, false)]
+    #[case(authenticator(), TIME - DEFAULT_MAX_CLOCK_SKEW.as_secs(), true)]
+    #[case(authenticator(), TIME - DEFAULT_MAX_CLOCK_SKEW.as_secs() - 1, false)]
+    #[case(authenticator().with_max_age(Duration::from_secs(60)), TIME + 60, true)]
+    #[case(authenticator().with_max_age(Duration::from_secs(60)), TIME + 61, false)]
+    #[case(authenticator().with_max_clock_skew(Duration::ZERO), TIME - 1, false)]
+    fn messages_must_be_signed_within_the_max_age_and_clock_skew(
+        #[case] authenticator: MessageAuthenticator,
+        #[case] now: u64,
+        #[case] accepted: bool,
+    ) {
+        let headers = MessageHeaders::sign(&signer(), "orders", b"{}", TIME)
+            .unwrap()
+            .to_headers();
+
+        let result =
+            authenticator.authenticate_at("orders", b"{}", header(&headers), signed_at(now));
+
+        match accepted {
+            true => assert!(result.is_ok()),
+            false => assert!(matches!(result, Err(Error::TimestampOutOfRange(TIME)))),
+        }
+    }
+
+    #[test]
+    fn unrepresentable_times_are_rejected() {
+        let time = u64::MAX.to_string();
+        let headers = [
+            (MESSAGE_AUTHENTICATION, "MWSMSG app:signature".to_owned()),
+            (MESSAGE_TIME, time.clone()),
+        ];
+
+        assert!(matches!(
+            authenticator().authenticate("orders", b"{}", header(&headers)),
+            Err(Error::InvalidAuthenticationHeader(_))
+        ));
+        assert!(matches!(
+            authenticator().authenticator().verify_message_signature(
+                APP_UUID,
+                "orders",
+                b"{}",
+                time,
+                "signature"
+            ),
+            Err(Error::InvalidAuthenticationHeader(_))
+        ));
+    }
+
+    #[test]
+    fn parse_rejects_malformed_headers() {
+        assert!(matches!(
+            MessageHeaders::parse(|_| None),
+            Err(Error::MissingAuthentication)
+        ));
+        for (authentication, time) in [
+            ("MWSV2 app:signature;", "1"),
+            ("MWSMSG :signature", "1"),
+            ("MWSMSG app:signature", "soon"),
+        ] {
+            let result = MessageHeaders::parse(|name| match name {
+                MESSAGE_AUTHENTICATION => Some(authentication),
+                MESSAGE_TIME => Some(time),
+                _ => None,
+            });
+            assert!(matches!(result, Err(Error::InvalidAuthenticationHeader(_))));
+        }
+    }
+}
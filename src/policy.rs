@@ -0,0 +1,410 @@
+use crate::error::Error;
+use crate::http::AuthenticatedApp;
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::Arc;
+
+/// Whether the server integrations authenticate requests to a route, and what they do with
+/// requests that fail
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Enforcement {
+    /// Reject the request
+    #[default]
+    Enforce,
+    /// Report the failure and let the request through, to see who would be rejected before
+    /// enforcing authentication on an existing API
+    Shadow,
+    /// Do not authenticate requests at all, for routes such as health checks and metrics
+    Exempt,
+}
+
+/// A request that failed authentication, as passed to the failure handler of an `AuthPolicy`
+#[derive(Debug)]
+pub struct AuthenticationFailure<'a> {
+    /// The HTTP method of the request
+    pub method: &'a str,
+    /// The path of the request
+    pub path: &'a str,
+    /// The app the request claimed to come from, if its MAuth headers could be parsed
+    pub app_uuid: Option<&'a str>,
+    /// Whether the request is being rejected or let through
+    pub enforcement: Enforcement,
+    /// Why authentication failed
+    pub error: &'a Error,
+}
+
+type FailureHandler = Arc<dyn Fn(&AuthenticationFailure<'_>) + Send + Sync>;
+
+/// Decides, per route, whether the server integrations authenticate requests, which apps may
+/// access the route, and how requests that fail are treated, and reports those failures.
+///
+/// Routes are matched by patterns made of an optional HTTP method and a path, such as
+/// `GET /items/*` or `/admin/**`. A `*` segment matches any single path segment and a final `**`
+/// segment matches any number of them, including none. The first matching route wins, and
+/// requests that match no route get the default enforcement. Request paths are matched as they
+/// were received, in the same way as the routers of axum, actix-web and tonic, without being
+/// percent-decoded or having their `.` and `..` segments resolved. As a server or proxy in front of
+/// the app may still resolve them, requests whose path has a `.` or `..` segment, even when
+/// percent-encoded, or a percent-encoded `/` are always enforced, whatever route they match.
+///
+/// Routes can also be restricted to an allowlist of app UUIDs. An authenticated app that is not on
+/// the allowlist of the first matching restricted route fails with `Error::AppNotAllowed`, which
+/// the integrations reject with `403 Forbidden` rather than `401 Unauthorized`. Apps must be on
+/// every allowlist to make requests with such ambiguous paths.
+///
+/// Failures are passed to the failure handler if one is set. Otherwise, they are logged with the
+/// `log` crate, at warning level for shadowed routes and debug level for enforced ones.
+///
+/// ```
+/// # use mauth_core::policy::{AuthPolicy, Enforcement};
+/// let mut policy = AuthPolicy::new(Enforcement::Enforce);
+/// policy.route("/reports/**", Enforcement::Shadow).unwrap();
+/// policy.route("POST /items", Enforcement::Shadow).unwrap();
+/// policy.route("GET /health", Enforcement::Exempt).unwrap();
+/// policy.allow_apps("/admin/**", ["101c139a-236c-11ef-b5e3-125eb8485a60"]).unwrap();
+///
+/// assert_eq!(policy.enforcement("GET", "/reports/2024/q1"), Enforcement::Shadow);
+/// assert_eq!(policy.enforcement("GET", "/items"), Enforcement::Enforce);
+/// assert!(policy.is_allowed("101c139a-236c-11ef-b5e3-125eb8485a60", "DELETE", "/admin/users/1"));
+/// assert!(!policy.is_allowed("d3d52c38-236c-11ef-a5f2-125eb8485a60", "DELETE", "/admin/users/1"));
+/// ```
+#[derive(Clone, Default)]
+pub struct AuthPolicy {
+    default: Enforcement,
+    routes: Vec<(RoutePattern, Enforcement)>,
+    allowlists: Vec<(RoutePattern, HashSet<String>)>,
+    failure_handler: Option<FailureHandler>,
+}
+
+impl fmt::Debug for AuthPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AuthPolicy")
+            .field("default", &self.default)
+            .field("routes", &self.routes)
+            .field("allowlists", &self.allowlists)
+            .finish_non_exhaustive()
+    }
+}
+
+impl AuthPolicy {
+    /// Initialize a policy that applies `default` to every route
+    pub fn new(default: Enforcement) -> Self {
+        Self {
+            default,
+            ..Self::default()
+        }
+    }
+
+    /// Apply `enforcement` to requests matching `pattern`, returning `Error::InvalidRoutePattern`
+    /// if the pattern is not an optional method followed by a path starting with `/`.
+    pub fn route(&mut self, pattern: &str, enforcement: Enforcement) -> Result<(), Error> {
+        self.routes
+            .push((RoutePattern::parse(pattern)?, enforcement));
+        Ok(())
+    }
+
+    /// Only allow the apps in `app_uuids` to access routes matching `pattern`, returning
+    /// `Error::InvalidRoutePattern` if the pattern is invalid.
+    pub fn allow_apps<I>(&mut self, pattern: &str, app_uuids: I) -> Result<(), Error>
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        let app_uuids = app_uuids.into_iter().map(Into::into).collect();
+        self.allowlists
+            .push((RoutePattern::parse(pattern)?, app_uuids));
+        Ok(())
+    }
+
+    /// Pass failures to `handler` instead of logging them
+    pub fn with_failure_handler(
+        mut self,
+        handler: impl Fn(&AuthenticationFailure<'_>) + Send + Sync + 'static,
+    ) -> Self {
+        self.failure_handler = Some(Arc::new(handler));
+        self
+    }
+
+    /// The enforcement that applies to a request, which is always `Enforcement::Enforce` for a
+    /// path with `.` or `..` segments or a percent-encoded `/`
+    pub fn enforcement(&self, method: &str, path: &str) -> Enforcement {
+        if is_ambiguous(path) {
+            return Enforcement::Enforce;
+        }
+        self.routes
+            .iter()
+            .find(|(pattern, _)| pattern.matches(method, path))
+            .map_or(self.default, |(_, enforcement)| *enforcement)
+    }
+
+    /// Whether an app may access a route, which is the case unless the first restricted route
+    /// matching the request does not list it. Requests with a path that `AuthPolicy::enforcement`
+    /// always enforces are only allowed for apps on every allowlist.
+    pub fn is_allowed(&self, app_uuid: &str, method: &str, path: &str) -> bool {
+        if is_ambiguous(path) {
+            return self
+                .allowlists
+                .iter()
+                .all(|(_, app_uuids)| app_uuids.contains(app_uuid));
+        }
+        self.allowlists
+            .iter()
+            .find(|(pattern, _)| pattern.matches(method, path))
+            .is_none_or(|(_, app_uuids)| app_uuids.contains(app_uuid))
+    }
+
+    /// Apply the policy to the result of authenticating a request. The authenticated app is
+    /// returned if authentication succeeded and the app is allowed on the route. Otherwise, the
+    /// failure is reported and, depending on the route's enforcement, either the error is returned
+    /// so that the request can be rejected, or `None` is returned and the request should be let
+    /// through. Requests to exempt routes should not be authenticated at all, and always get
+    /// `None`.
+    pub fn apply(
+        &self,
+        method: &str,
+        path: &str,
+        app_uuid: Option<&str>,
+        result: Result<AuthenticatedApp, Error>,
+    ) -> Result<Option<AuthenticatedApp>, Error> {
+        let enforcement = self.enforcement(method, path);
+        let error = match result {
+            _ if enforcement == Enforcement::Exempt => return Ok(None),
+            Ok(app) if self.is_allowed(&app.app_uuid, method, path) => return Ok(Some(app)),
+            Ok(app) => Error::AppNotAllowed(app.app_uuid),
+            Err(error) => error,
+        };
+        self.report(&AuthenticationFailure {
+            method,
+            path,
+            app_uuid,
+            enforcement,
+            error: &error,
+        });
+
+        match enforcement {
+            Enforcement::Enforce => Err(error),
+            Enforcement::Shadow | Enforcement::Exempt => Ok(None),
+        }
+    }
+
+    fn report(&self, failure: &AuthenticationFailure<'_>) {
+        if let Some(handler) = &self.failure_handler {
+            return handler(failure);
+        }
+
+        let level = match failure.enforcement {
+            Enforcement::Enforce => log::Level::Debug,
+            Enforcement::Shadow | Enforcement::Exempt => log::Level::Warn,
+        };
+        log::log!(
+            level,
+            "MAuth authentication failed for {} {} from app {} ({:?}): {}",
+            failure.method,
+            failure.path,
+            failure.app_uuid.unwrap_or("unknown"),
+            failure.enforcement,
+            failure.error
+        );
+    }
+}
+
+/// A method and path pattern that a route applies to
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct RoutePattern {
+    method: Option<String>,
+    segments: Vec<String>,
+}
+
+impl RoutePattern {
+    fn parse(pattern: &str) -> Result<Self, Error> {
+        let invalid = || Error::InvalidRoutePattern(pattern.to_owned());
+        let (method, path) = match pattern.trim().split_once(char::is_whitespace) {
+            Some((method, path)) => (Some(method.to_ascii_uppercase()), path.trim_start()),
+            None => (None, pattern.trim()),
+        };
+        if !path.starts_with('/') {
+            return Err(invalid());
+        }
+
+        let segments: Vec<String> = split_path(path).map(str::to_owned).collect();
+        let last = segments.len().saturating_sub(1);
+        if segments
+            .iter()
+            .position(|s| s == "**")
+            .is_some_and(|i| i != last)
+        {
+            return Err(invalid());
+        }
+
+        Ok(Self { method, segments })
+    }
+
+    fn matches(&self, method: &str, path: &str) -> bool {
+        if self
+            .method
+            .as_ref()
+            .is_some_and(|m| !m.eq_ignore_ascii_case(method))
+        {
+            return false;
+        }
+
+        let mut path = split_path(path);
+        for segment in &self.segments {
+            match (segment.as_str(), path.next()) {
+                ("**", _) => return true,
+                (_, None) => return false,
+                ("*", Some(_)) => {}
+                (expected, Some(actual)) if expected == actual => {}
+                _ => return false,
+            }
+        }
+        path.next().is_none()
+    }
+}
+
+fn split_path(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|segment| !segment.is_empty())
+}
+
+/// Whether a request path has segments that servers and proxies may resolve before it reaches the
+/// router, so that the route it is matched against may not be the one that handles it
+fn is_ambiguous(path: &str) -> bool {
+    split_path(path).any(|segment| {
+        let segment = urlencoding::decode(segment).unwrap_or(Cow::Borrowed(segment));
+        matches!(segment.as_ref(), "." | "..") || segment.contains('/')
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use std::sync::Mutex;
+
+    #[rstest]
+    #[case("/health", "GET", "/health", true)]
+    #[case("/health", "GET", "/health/", true)]
+    #[case("/health", "GET", "/healthz", false)]
+    #[case("GET /items/*", "get", "/items/1", true)]
+    #[case("GET /items/*", "GET", "/items/1/parts", false)]
+    #[case("GET /items/*", "POST", "/items/1", false)]
+    #[case("/admin/**", "DELETE", "/admin", true)]
+    #[case("/admin/**", "DELETE", "/admin/users/1", true)]
+    #[case("/admin/**", "DELETE", "/administrator", false)]
+    #[case("/", "GET", "/", true)]
+    #[case("/admin/**", "GET", "/public/../admin/users", false)]
+    #[case("/admin/**", "GET", "/%61dmin", false)]
+    fn route_patterns_match(
+        #[case] pattern: &str,
+        #[case] method: &str,
+        #[case] path: &str,
+        #[case] expected: bool,
+    ) {
+        assert_eq!(
+            RoutePattern::parse(pattern).unwrap().matches(method, path),
+            expected
+        );
+    }
+
+    #[rstest]
+    #[case("health")]
+    #[case("GET health")]
+    #[case("/a/**/b")]
+    fn invalid_route_patterns_are_rejected(#[case] pattern: &str) {
+        assert!(matches!(
+            RoutePattern::parse(pattern),
+            Err(Error::InvalidRoutePattern(_))
+        ));
+    }
+
+    #[rstest]
+    #[case("/public/../admin")]
+    #[case("/public/%2e%2E/admin")]
+    #[case("/public/./health")]
+    #[case("/public/admin%2Fusers")]
+    fn ambiguous_paths_are_always_enforced(#[case] path: &str) {
+        let mut policy = AuthPolicy::new(Enforcement::Exempt);
+        policy.route("/public/**", Enforcement::Exempt).unwrap();
+        policy.allow_apps("/admin/**", ["admin-app"]).unwrap();
+        policy
+            .allow_apps("/public/**", ["admin-app", "public-app"])
+            .unwrap();
+
+        assert_eq!(policy.enforcement("GET", path), Enforcement::Enforce);
+        assert!(policy.is_allowed("admin-app", "GET", path));
+        assert!(!policy.is_allowed("public-app", "GET", path));
+        assert_eq!(
+            policy.enforcement("GET", "/public/health"),
+            Enforcement::Exempt
+        );
+        assert!(policy.is_allowed("public-app", "GET", "/public/health"));
+    }
+
+    #[test]
+    fn apply_rejects_apps_missing_from_the_allowlist() {
+        let app = |app_uuid: &str| AuthenticatedApp {
+            app_uuid: app_uuid.to_owned(),
+            key_fingerprint: crate::keys::tests::signer().fingerprint(),
+            version: 2,
+        };
+        let mut policy = AuthPolicy::default();
+        policy.route("GET /health", Enforcement::Exempt).unwrap();
+        policy.allow_apps("/admin/**", ["admin-app"]).unwrap();
+
+        assert!(matches!(
+            policy.apply("POST", "/admin/users", None, Ok(app("admin-app"))),
+            Ok(Some(_))
+        ));
+        assert!(matches!(
+            policy.apply("POST", "/admin/users", None, Ok(app("other-app"))),
+            Err(Error::AppNotAllowed(app_uuid)) if app_uuid == "other-app"
+        ));
+        assert!(matches!(
+            policy.apply("POST", "/items", None, Ok(app("other-app"))),
+            Ok(Some(_))
+        ));
+        assert!(matches!(
+            policy.apply("GET", "/health", None, Err(Error::MissingAuthentication)),
+            Ok(None)
+        ));
+    }
+
+    #[test]
+    fn apply_reports_failures_and_lets_shadowed_routes_through() {
+        let reported = Arc::new(Mutex::new(Vec::new()));
+        let mut policy = AuthPolicy::new(Enforcement::Enforce).with_failure_handler({
+            let reported = reported.clone();
+            move |failure| {
+                reported.lock().unwrap().push((
+                    failure.path.to_owned(),
+                    failure.app_uuid.map(str::to_owned),
+                    failure.enforcement,
+                ))
+            }
+        });
+        policy.route("/beta/**", Enforcement::Shadow).unwrap();
+
+        let shadowed = policy.apply(
+            "GET",
+            "/beta/items",
+            Some("app"),
+            Err(Error::MissingAuthentication),
+        );
+        assert!(matches!(shadowed, Ok(None)));
+        let enforced = policy.apply("GET", "/items", None, Err(Error::MissingAuthentication));
+        assert!(matches!(enforced, Err(Error::MissingAuthentication)));
+
+        assert_eq!(
+            *reported.lock().unwrap(),
+            [
+                (
+                    "/beta/items".to_owned(),
+                    Some("app".to_owned()),
+                    Enforcement::Shadow
+                ),
+                ("/items".to_owned(), None, Enforcement::Enforce),
+            ]
+        );
+    }
+}
@@ -0,0 +1,264 @@
+use crate::error::Error;
+use crate::signer::{RotatingSigner, Signer};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Chooses the signer for an outgoing request, so that the client integrations can sign with a
+/// single `Signer`, the current key of a `RotatingSigner`, or the signer a `SignerRegistry`
+/// selects for the destination.
+pub trait SignerSelector: Send + Sync {
+    /// The signer for a request to `host` and `path`, or `None` if there is none
+    fn select_signer(&self, host: Option<&str>, path: &str) -> Option<Signer>;
+}
+
+impl SignerSelector for Signer {
+    fn select_signer(&self, _host: Option<&str>, _path: &str) -> Option<Signer> {
+        Some(self.clone())
+    }
+}
+
+impl SignerSelector for RotatingSigner {
+    fn select_signer(&self, _host: Option<&str>, _path: &str) -> Option<Signer> {
+        Some(self.current())
+    }
+}
+
+impl SignerSelector for SignerRegistry {
+    fn select_signer(&self, host: Option<&str>, path: &str) -> Option<Signer> {
+        self.select(host, path).cloned()
+    }
+}
+
+impl<T: SignerSelector + ?Sized> SignerSelector for Arc<T> {
+    fn select_signer(&self, host: Option<&str>, path: &str) -> Option<Signer> {
+        (**self).select_signer(host, path)
+    }
+}
+
+/// Holds the signers for a service that acts as more than one MAuth app, and selects which one to
+/// use for an outgoing request based on its destination.
+///
+/// Signers are stored under a label, which is their app UUID unless a label is given explicitly.
+/// Routes are checked in the order they were added and the first match wins. If no route matches,
+/// the default signer is used, if one has been set.
+///
+/// ```
+/// # use mauth_core::keys::{KeyPair, KeySize, PrivateKeyFormat};
+/// # use mauth_core::registry::SignerRegistry;
+/// # use mauth_core::signer::Signer;
+/// # let new_signer = |app_uuid| Signer::new(app_uuid, KeyPair::generate(KeySize::Rsa2048).unwrap().private_key_pem(PrivateKeyFormat::Pkcs1).unwrap().to_string()).unwrap();
+/// let mut registry = SignerRegistry::new();
+/// registry.insert(new_signer("101c139a-236c-11ef-b5e3-125eb8485a60"));
+/// registry.insert_with_label("tenant-a", new_signer("8ac278af-e761-479b-9e7a-10bcc2f30304"));
+/// registry.route_host("tenant-a.example.com", "tenant-a").unwrap();
+/// registry.set_default("101c139a-236c-11ef-b5e3-125eb8485a60").unwrap();
+///
+/// let signer = registry.select(Some("tenant-a.example.com"), "/items").unwrap();
+/// assert_eq!(signer.app_uuid(), "8ac278af-e761-479b-9e7a-10bcc2f30304");
+/// let signer = registry.select(Some("other.example.com"), "/items").unwrap();
+/// assert_eq!(signer.app_uuid(), "101c139a-236c-11ef-b5e3-125eb8485a60");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct SignerRegistry {
+    signers: HashMap<String, Signer>,
+    routes: Vec<(Route, String)>,
+    default: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+enum Route {
+    Host(String),
+    PathPrefix(String),
+}
+
+impl Route {
+    fn matches(&self, host: Option<&str>, path: &str) -> bool {
+        match self {
+            Route::Host(pattern) => host.is_some_and(|host| host_matches(pattern, host)),
+            Route::PathPrefix(prefix) => path_has_prefix(path, prefix),
+        }
+    }
+}
+
+impl SignerRegistry {
+    /// Initialize an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a signer labelled with its app UUID, replacing any signer with the same label
+    pub fn insert(&mut self, signer: Signer) {
+        self.signers.insert(signer.app_uuid().to_owned(), signer);
+    }
+
+    /// Add a signer under a caller-defined label, replacing any signer with the same label
+    pub fn insert_with_label(&mut self, label: impl Into<String>, signer: Signer) {
+        self.signers.insert(label.into(), signer);
+    }
+
+    /// The signer stored under a label
+    pub fn get(&self, label: &str) -> Option<&Signer> {
+        self.signers.get(label)
+    }
+
+    /// Use the labelled signer for requests to a host. The host is compared case-insensitively and
+    /// without its port. A pattern starting with `*.` matches any subdomain of the rest of the
+    /// pattern, but not the domain itself.
+    pub fn route_host(&mut self, host: impl Into<String>, label: &str) -> Result<(), Error> {
+        self.add_route(Route::Host(host.into().to_ascii_lowercase()), label)
+    }
+
+    /// Use the labelled signer for requests whose path starts with the prefix. The prefix only
+    /// matches whole path segments, so `/api` matches `/api` and `/api/items` but not `/apis`.
+    pub fn route_path_prefix(
+        &mut self,
+        prefix: impl Into<String>,
+        label: &str,
+    ) -> Result<(), Error> {
+        self.add_route(Route::PathPrefix(prefix.into()), label)
+    }
+
+    /// Use the labelled signer for requests that do not match any route
+    pub fn set_default(&mut self, label: &str) -> Result<(), Error> {
+        self.check_label(label)?;
+        self.default = Some(label.to_owned());
+
+        Ok(())
+    }
+
+    /// Select the signer for a request to the given host and path, or `None` if no route matches
+    /// and there is no default signer.
+    pub fn select(&self, host: Option<&str>, path: &str) -> Option<&Signer> {
+        let host = host.map(strip_port);
+        self.routes
+            .iter()
+            .find(|(route, _)| route.matches(host, path))
+            .map(|(_, label)| label)
+            .or(self.default.as_ref())
+            .and_then(|label| self.signers.get(label))
+    }
+
+    fn add_route(&mut self, route: Route, label: &str) -> Result<(), Error> {
+        self.check_label(label)?;
+        self.routes.push((route, label.to_owned()));
+
+        Ok(())
+    }
+
+    fn check_label(&self, label: &str) -> Result<(), Error> {
+        match self.signers.contains_key(label) {
+            true => Ok(()),
+            false => Err(Error::UnknownSigner(label.to_owned())),
+        }
+    }
+}
+
+fn strip_port(host: &str) -> &str {
+    if let Some(end) = host.find(']') {
+        return &host[..=end];
+    }
+    match host.rsplit_once(':') {
+        Some((name, port)) if !port.is_empty() && port.bytes().all(|b| b.is_ascii_digit()) => name,
+        _ => host,
+    }
+}
+
+fn host_matches(pattern: &str, host: &str) -> bool {
+    match pattern.strip_prefix("*.") {
+        Some(domain) => host
+            .len()
+            .checked_sub(domain.len() + 1)
+            .is_some_and(|split| {
+                host.as_bytes()[split] == b'.' && host[split + 1..].eq_ignore_ascii_case(domain)
+            }),
+        None => host.eq_ignore_ascii_case(pattern),
+    }
+}
+
+fn path_has_prefix(path: &str, prefix: &str) -> bool {
+    let prefix = prefix.trim_end_matches('/');
+    match path.strip_prefix(prefix) {
+        Some(rest) => rest.is_empty() || rest.starts_with('/') || prefix.is_empty(),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::tests::key_pair;
+    use crate::keys::PrivateKeyFormat;
+    use rstest::rstest;
+
+    fn signer(app_uuid: &str) -> Signer {
+        let private_key = key_pair().private_key_pem(PrivateKeyFormat::Pkcs1).unwrap();
+        Signer::new(app_uuid, private_key.to_string()).unwrap()
+    }
+
+    #[test]
+    fn selectors_choose_a_signer() {
+        let selectors: [Arc<dyn SignerSelector>; 3] = [
+            Arc::new(signer("host-app")),
+            Arc::new(RotatingSigner::new(signer("host-app"))),
+            Arc::new(registry()),
+        ];
+
+        for selector in selectors {
+            let signer = selector
+                .select_signer(Some("api.example.com"), "/")
+                .unwrap();
+            assert_eq!(signer.app_uuid(), "host-app");
+        }
+    }
+
+    fn registry() -> SignerRegistry {
+        let mut registry = SignerRegistry::new();
+        registry.insert(signer("default-app"));
+        registry.insert_with_label("host", signer("host-app"));
+        registry.insert_with_label("wildcard", signer("wildcard-app"));
+        registry.insert_with_label("path", signer("path-app"));
+        registry.route_host("api.example.com", "host").unwrap();
+        registry
+            .route_host("*.tenants.example.com", "wildcard")
+            .unwrap();
+        registry.route_path_prefix("/reports/", "path").unwrap();
+        registry.set_default("default-app").unwrap();
+        registry
+    }
+
+    #[rstest(
+        host,
+        path,
+        expected,
+        case(Some("api.example.com"), "/reports/1", "host-app"),
+        case(Some("API.example.com:8443"), "/", "host-app"),
+        case(Some("a.tenants.example.com"), "/", "wildcard-app"),
+        case(Some("tenants.example.com"), "/", "default-app"),
+        case(Some("other.example.com"), "/reports", "path-app"),
+        case(None, "/reports/1", "path-app"),
+        case(None, "/reportsx", "default-app")
+    )]
+    fn select_test(host: Option<&str>, path: &str, expected: &str) {
+        assert_eq!(registry().select(host, path).unwrap().app_uuid(), expected);
+    }
+
+    #[test]
+    fn select_without_default_returns_none() {
+        let mut registry = SignerRegistry::new();
+        registry.insert(signer("app"));
+        registry.route_host("api.example.com", "app").unwrap();
+
+        assert!(registry.select(Some("other.example.com"), "/").is_none());
+    }
+
+    #[test]
+    fn routes_require_a_known_label() {
+        let mut registry = SignerRegistry::new();
+
+        assert!(matches!(
+            registry.route_host("api.example.com", "missing"),
+            Err(Error::UnknownSigner(label)) if label == "missing"
+        ));
+        assert!(registry.set_default("missing").is_err());
+    }
+}
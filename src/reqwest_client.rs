@@ -0,0 +1,519 @@
+use crate::error::Error;
+use crate::http::{
+    signature_headers, HostAllowlist, RequestAuthenticator, SigningVersions, DEFAULT_BODY_LIMIT,
+    MAUTH_HEADERS,
+};
+use crate::registry::SignerSelector;
+use bytes::BytesMut;
+use http::Extensions;
+use reqwest::{Request, Response, ResponseBuilderExt};
+use reqwest_middleware::{Middleware, Next};
+use std::fmt;
+use std::sync::Arc;
+
+/// `reqwest-middleware` middleware that signs each outgoing request with MAuth, using the current
+/// time.
+///
+/// The signer is chosen for each request by a `registry::SignerSelector`, which can be a single
+/// `Signer`, a `RotatingSigner` or a `SignerRegistry`. Requests that no signer is selected for fail
+/// with `Error::NoSignerSelected`. Any MAuth headers already on a request are replaced, so when
+/// this is added after a retry middleware every attempt is signed again with a fresh timestamp.
+/// Requests with a streaming body cannot be signed and fail with `Error::BodyReadError`.
+///
+/// Every request is signed unless a `http::HostAllowlist` is set, which should be done for clients
+/// that may send requests to third parties.
+///
+/// With `SigningMiddleware::with_response_verification`, the responses to signed requests must be
+/// signed too. Their bodies are buffered, up to `http::DEFAULT_BODY_LIMIT` bytes unless set with
+/// `SigningMiddleware::with_body_limit`, and verified on tokio's blocking thread pool. The
+/// `http::AuthenticatedApp` that signed a response is inserted into its extensions. Any app known
+/// to the authenticator is accepted, including other clients of the same service, unless the app
+/// expected to respond is pinned with `SigningMiddleware::with_expected_responder` or, for a single
+/// request, an `ExpectedResponder` extension.
+///
+/// ```
+/// # use mauth_core::http::SigningVersions;
+/// # use mauth_core::keys::{KeyPair, KeySize, PrivateKeyFormat};
+/// # use mauth_core::reqwest_client::SigningMiddleware;
+/// # use mauth_core::signer::Signer;
+/// # let signer = Signer::new("101c139a-236c-11ef-b5e3-125eb8485a60", KeyPair::generate(KeySize::Rsa2048).unwrap().private_key_pem(PrivateKeyFormat::Pkcs1).unwrap().to_string()).unwrap();
+/// let client = reqwest_middleware::ClientBuilder::new(reqwest::Client::new())
+///     .with(SigningMiddleware::new(signer).with_versions(SigningVersions::Both))
+///     .build();
+/// ```
+#[derive(Clone)]
+pub struct SigningMiddleware {
+    selector: Arc<dyn SignerSelector>,
+    versions: SigningVersions,
+    allowlist: Option<Arc<HostAllowlist>>,
+    response_authenticator: Option<Arc<RequestAuthenticator>>,
+    body_limit: usize,
+    expected_responder: Option<String>,
+}
+
+/// A request extension pinning the app that must sign the response to a single request, in place of
+/// the one set with `SigningMiddleware::with_expected_responder`
+///
+/// ```
+/// # use mauth_core::reqwest_client::ExpectedResponder;
+/// # let client = reqwest_middleware::ClientBuilder::new(reqwest::Client::new()).build();
+/// let request = client
+///     .get("https://api.example.com/items")
+///     .with_extension(ExpectedResponder("101c139a-236c-11ef-b5e3-125eb8485a60".to_owned()));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExpectedResponder(pub String);
+
+impl fmt::Debug for SigningMiddleware {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SigningMiddleware")
+            .field("versions", &self.versions)
+            .field("allowlist", &self.allowlist)
+            .field("response_authenticator", &self.response_authenticator)
+            .field("body_limit", &self.body_limit)
+            .field("expected_responder", &self.expected_responder)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SigningMiddleware {
+    /// Initialize middleware that signs requests with the signer chosen by `selector`, using V2
+    pub fn new(selector: impl SignerSelector + 'static) -> Self {
+        Self {
+            selector: Arc::new(selector),
+            versions: SigningVersions::default(),
+            allowlist: None,
+            response_authenticator: None,
+            body_limit: DEFAULT_BODY_LIMIT,
+            expected_responder: None,
+        }
+    }
+
+    /// Set the MAuth protocol versions requests are signed with
+    pub fn with_versions(mut self, versions: SigningVersions) -> Self {
+        self.versions = versions;
+        self
+    }
+
+    /// Only sign requests to the hosts on `allowlist`
+    pub fn with_allowlist(mut self, allowlist: HostAllowlist) -> Self {
+        self.allowlist = Some(Arc::new(allowlist));
+        self
+    }
+
+    /// Reject the responses to signed requests unless they are signed by an app known to
+    /// `authenticator`
+    pub fn with_response_verification(mut self, authenticator: RequestAuthenticator) -> Self {
+        self.response_authenticator = Some(Arc::new(authenticator));
+        self
+    }
+
+    /// Set the largest response body, in bytes, that will be buffered for verification. Larger
+    /// responses fail with `Error::BodyTooLarge`.
+    pub fn with_body_limit(mut self, body_limit: usize) -> Self {
+        self.body_limit = body_limit;
+        self
+    }
+
+    /// Only accept verified responses signed by `app_uuid`, failing with
+    /// `Error::UnexpectedResponder` for responses signed by any other app. This has no effect
+    /// unless responses are verified.
+    pub fn with_expected_responder(mut self, app_uuid: impl Into<String>) -> Self {
+        self.expected_responder = Some(app_uuid.into());
+        self
+    }
+
+    /// Sign a request, returning false if it is to be sent unsigned
+    fn sign(&self, request: &mut Request) -> Result<bool, Error> {
+        if let Some(allowlist) = &self.allowlist {
+            let url = request.url();
+            let sign = allowlist.should_sign(Some(url.scheme()), url.host_str(), || {
+                url.origin().ascii_serialization()
+            })?;
+            if !sign {
+                for name in MAUTH_HEADERS {
+                    request.headers_mut().remove(name);
+                }
+                return Ok(false);
+            }
+        }
+
+        let body = match request.body() {
+            Some(body) => body
+                .as_bytes()
+                .ok_or_else(|| Error::BodyReadError("streaming bodies cannot be signed".into()))?,
+            None => &[],
+        };
+        let url = request.url();
+        let signer = self
+            .selector
+            .select_signer(url.host_str(), url.path())
+            .ok_or_else(|| Error::NoSignerSelected(url.origin().ascii_serialization()))?;
+        let headers = signature_headers(
+            &signer,
+            self.versions,
+            request.method().as_str(),
+            url.path(),
+            url.query().unwrap_or_default(),
+            body,
+        )?;
+
+        for (name, value) in headers {
+            request.headers_mut().insert(name, value);
+        }
+
+        Ok(true)
+    }
+}
+
+async fn verify_response(
+    authenticator: Arc<RequestAuthenticator>,
+    body_limit: usize,
+    expected_responder: Option<String>,
+    mut response: Response,
+) -> Result<Response, Error> {
+    let url = response.url().clone();
+    let status = response.status();
+    let version = response.version();
+    let headers = std::mem::take(response.headers_mut());
+    let extensions = std::mem::take(response.extensions_mut());
+    let mut body = BytesMut::new();
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| Error::BodyReadError(Box::new(e)))?
+    {
+        if body.len() + chunk.len() > body_limit {
+            return Err(Error::BodyTooLarge(body_limit));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    let body = body.freeze();
+
+    let (headers, body, app) = tokio::task::spawn_blocking(move || {
+        let app = authenticator.authenticate_response(status.as_u16(), &body, |name| {
+            headers.get(name)?.to_str().ok()
+        });
+        (headers, body, app)
+    })
+    .await
+    .map_err(|e| Error::IoError(std::io::Error::other(e)))?;
+    let app = app?;
+    if let Some(expected) = expected_responder {
+        if app.app_uuid != expected {
+            return Err(Error::UnexpectedResponder {
+                expected,
+                actual: app.app_uuid,
+            });
+        }
+    }
+
+    let mut verified = http::Response::builder()
+        .url(url)
+        .body(body)
+        .expect("a response builder without parsed parts cannot fail");
+    *verified.status_mut() = status;
+    *verified.version_mut() = version;
+    *verified.headers_mut() = headers;
+    verified.extensions_mut().extend(extensions);
+    verified.extensions_mut().insert(app);
+
+    Ok(verified.into())
+}
+
+#[async_trait::async_trait]
+impl Middleware for SigningMiddleware {
+    async fn handle(
+        &self,
+        mut request: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        let signed = self
+            .sign(&mut request)
+            .map_err(reqwest_middleware::Error::middleware)?;
+        let expected_responder = extensions
+            .get::<ExpectedResponder>()
+            .map(|ExpectedResponder(app_uuid)| app_uuid.clone())
+            .or_else(|| self.expected_responder.clone());
+        let response = next.run(request, extensions).await?;
+
+        match &self.response_authenticator {
+            Some(authenticator) if signed => verify_response(
+                authenticator.clone(),
+                self.body_limit,
+                expected_responder,
+                response,
+            )
+            .await
+            .map_err(reqwest_middleware::Error::middleware),
+            _ => Ok(response),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::tests::authenticator;
+    use crate::http::{AuthenticatedApp, UnlistedHosts, X_MWS_AUTHENTICATION};
+    use crate::keys::tests::{signer, APP_UUID};
+    use reqwest_middleware::ClientBuilder;
+    use std::sync::{Arc, Mutex};
+
+    /// Records the requests that reach the end of the middleware chain instead of sending them
+    #[derive(Default, Clone)]
+    struct Capture(Arc<Mutex<Vec<Request>>>);
+
+    #[async_trait::async_trait]
+    impl Middleware for Capture {
+        async fn handle(
+            &self,
+            request: Request,
+            _: &mut Extensions,
+            _: Next<'_>,
+        ) -> reqwest_middleware::Result<Response> {
+            self.0.lock().unwrap().push(request);
+            Ok(Response::from(http::Response::new("")))
+        }
+    }
+
+    /// Sends every request twice, like a retry middleware after a failed attempt
+    struct Twice;
+
+    #[async_trait::async_trait]
+    impl Middleware for Twice {
+        async fn handle(
+            &self,
+            request: Request,
+            extensions: &mut Extensions,
+            next: Next<'_>,
+        ) -> reqwest_middleware::Result<Response> {
+            let retry = request.try_clone().unwrap();
+            next.clone().run(request, extensions).await?;
+            next.run(retry, extensions).await
+        }
+    }
+
+    fn authenticate(request: &Request) -> Result<u8, Error> {
+        let body = request
+            .body()
+            .and_then(|body| body.as_bytes())
+            .unwrap_or_default();
+        RequestAuthenticator::new(authenticator())
+            .authenticate(
+                request.method().as_str(),
+                request.url().path(),
+                request.url().query().unwrap_or_default(),
+                body,
+                |name| request.headers().get(name)?.to_str().ok(),
+            )
+            .map(|app| app.version)
+    }
+
+    #[tokio::test]
+    async fn every_attempt_is_signed() {
+        let capture = Capture::default();
+        let client = ClientBuilder::new(reqwest::Client::new())
+            .with(Twice)
+            .with(SigningMiddleware::new(signer()).with_versions(SigningVersions::Both))
+            .with(capture.clone())
+            .build();
+
+        client
+            .post("http://example.com/items?b=2&a=1")
+            .body("{}")
+            .send()
+            .await
+            .unwrap();
+
+        let requests = capture.0.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        for request in requests.iter() {
+            assert_eq!(authenticate(request).unwrap(), 2);
+            assert_eq!(
+                request
+                    .headers()
+                    .get_all(X_MWS_AUTHENTICATION)
+                    .iter()
+                    .count(),
+                1
+            );
+            assert!(request.headers()[X_MWS_AUTHENTICATION]
+                .to_str()
+                .unwrap()
+                .starts_with(&format!("MWS {APP_UUID}:")));
+        }
+    }
+
+    #[tokio::test]
+    async fn streaming_bodies_are_not_signed() {
+        let client = ClientBuilder::new(reqwest::Client::new())
+            .with(SigningMiddleware::new(signer()))
+            .with(Capture::default())
+            .build();
+        let body = reqwest::Body::wrap_stream(futures_util::stream::empty::<
+            Result<bytes::Bytes, std::io::Error>,
+        >());
+
+        let result = client.post("http://example.com/").body(body).send().await;
+
+        assert!(matches!(
+            result,
+            Err(reqwest_middleware::Error::Middleware(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn only_allowlisted_hosts_are_signed() {
+        let mut allowlist = HostAllowlist::new(UnlistedHosts::SendUnsigned);
+        allowlist.allow("http://*.example.com").unwrap();
+        let capture = Capture::default();
+        let client = ClientBuilder::new(reqwest::Client::new())
+            .with(SigningMiddleware::new(signer()).with_allowlist(allowlist))
+            .with(capture.clone())
+            .build();
+
+        client.get("http://api.example.com/").send().await.unwrap();
+        client
+            .get("http://example.org/")
+            .header(X_MWS_AUTHENTICATION, "MWS stale:signature")
+            .send()
+            .await
+            .unwrap();
+
+        let requests = capture.0.lock().unwrap();
+        assert!(authenticate(&requests[0]).is_ok());
+        assert!(matches!(
+            authenticate(&requests[1]),
+            Err(Error::MissingAuthentication)
+        ));
+    }
+
+    /// Responds to every request with a response signed at the current time
+    struct SignedResponse {
+        status: u16,
+        signed_status: u16,
+    }
+
+    #[async_trait::async_trait]
+    impl Middleware for SignedResponse {
+        async fn handle(
+            &self,
+            request: Request,
+            _: &mut Extensions,
+            _: Next<'_>,
+        ) -> reqwest_middleware::Result<Response> {
+            let time = crate::http::unix_time(std::time::SystemTime::now());
+            let headers = crate::http::sign_response(
+                &signer(),
+                SigningVersions::V2,
+                self.signed_status,
+                b"{}",
+                time,
+            )
+            .unwrap();
+            let mut response = http::Response::builder()
+                .status(self.status)
+                .url(request.url().clone());
+            for (name, value) in headers {
+                response = response.header(name, value);
+            }
+            let mut response = response.body("{}").unwrap();
+            response.extensions_mut().insert(Marker);
+            Ok(Response::from(response))
+        }
+    }
+
+    /// An extension added to responses before they are verified
+    #[derive(Debug, Clone, PartialEq)]
+    struct Marker;
+
+    #[tokio::test]
+    async fn responses_are_verified() {
+        let client = |signed_status| {
+            let middleware = SigningMiddleware::new(signer())
+                .with_response_verification(RequestAuthenticator::new(authenticator()));
+            ClientBuilder::new(reqwest::Client::new())
+                .with(middleware)
+                .with(SignedResponse {
+                    status: 201,
+                    signed_status,
+                })
+                .build()
+        };
+
+        let response = client(201).get("http://example.com/").send().await.unwrap();
+        assert_eq!(response.status(), 201);
+        assert_eq!(response.url().as_str(), "http://example.com/");
+        let app = response.extensions().get::<AuthenticatedApp>().unwrap();
+        assert_eq!(app.app_uuid, APP_UUID);
+        assert_eq!(response.extensions().get::<Marker>(), Some(&Marker));
+        assert_eq!(response.text().await.unwrap(), "{}");
+
+        let result = client(200).get("http://example.com/").send().await;
+        assert!(matches!(
+            result,
+            Err(reqwest_middleware::Error::Middleware(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn responses_over_the_body_limit_fail() {
+        let client = ClientBuilder::new(reqwest::Client::new())
+            .with(
+                SigningMiddleware::new(signer())
+                    .with_response_verification(RequestAuthenticator::new(authenticator()))
+                    .with_body_limit(1),
+            )
+            .with(SignedResponse {
+                status: 200,
+                signed_status: 200,
+            })
+            .build();
+
+        let result = client.get("http://example.com/").send().await;
+
+        let Err(reqwest_middleware::Error::Middleware(error)) = result else {
+            panic!("unexpected result: {result:?}");
+        };
+        assert!(matches!(
+            error.downcast_ref::<Error>(),
+            Some(Error::BodyTooLarge(1))
+        ));
+    }
+
+    #[tokio::test]
+    async fn responses_must_come_from_the_expected_responder() {
+        let client = ClientBuilder::new(reqwest::Client::new())
+            .with(
+                SigningMiddleware::new(signer())
+                    .with_response_verification(RequestAuthenticator::new(authenticator()))
+                    .with_expected_responder("other-app"),
+            )
+            .with(SignedResponse {
+                status: 200,
+                signed_status: 200,
+            })
+            .build();
+
+        let result = client.get("http://example.com/").send().await;
+        let Err(reqwest_middleware::Error::Middleware(error)) = result else {
+            panic!("unexpected result: {result:?}");
+        };
+        assert!(matches!(
+            error.downcast_ref::<Error>(),
+            Some(Error::UnexpectedResponder { expected, actual })
+                if expected == "other-app" && actual == APP_UUID
+        ));
+
+        let response = client
+            .get("http://example.com/")
+            .with_extension(ExpectedResponder(APP_UUID.to_owned()))
+            .send()
+            .await
+            .unwrap();
+        let app = response.extensions().get::<AuthenticatedApp>().unwrap();
+        assert_eq!(app.app_uuid, APP_UUID);
+    }
+}
@@ -0,0 +1,266 @@
+use crate::error::Error;
+use crate::keys::KeyFingerprint;
+use std::path::Path;
+use std::sync::{PoisonError, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A single revoked app or key. A revocation matches every key of an app, a specific key of any
+/// app, or a specific key of one app, from its effective time onwards.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Revocation {
+    app_uuid: Option<String>,
+    fingerprint: Option<KeyFingerprint>,
+    effective_at: SystemTime,
+}
+
+impl Revocation {
+    /// Revoke every key of an app, effective immediately
+    pub fn app(app_uuid: impl Into<String>) -> Self {
+        Self {
+            app_uuid: Some(app_uuid.into()),
+            fingerprint: None,
+            effective_at: UNIX_EPOCH,
+        }
+    }
+
+    /// Revoke a key, whichever app presents it, effective immediately
+    pub fn key(fingerprint: KeyFingerprint) -> Self {
+        Self {
+            app_uuid: None,
+            fingerprint: Some(fingerprint),
+            effective_at: UNIX_EPOCH,
+        }
+    }
+
+    /// Only revoke the key for the given app
+    pub fn for_app(mut self, app_uuid: impl Into<String>) -> Self {
+        self.app_uuid = Some(app_uuid.into());
+        self
+    }
+
+    /// Only apply the revocation from the given time onwards
+    pub fn effective_at(mut self, effective_at: SystemTime) -> Self {
+        self.effective_at = effective_at;
+        self
+    }
+
+    fn matches(&self, app_uuid: &str, fingerprint: &KeyFingerprint, now: SystemTime) -> bool {
+        now >= self.effective_at
+            && self.app_uuid.as_deref().is_none_or(|a| a == app_uuid)
+            && self.fingerprint.as_ref().is_none_or(|f| f == fingerprint)
+    }
+}
+
+/// A list of revoked apps and keys, consulted by `Verifier` and `Authenticator` after a signature
+/// has been verified. It can be shared between verifiers and updated in place, so a revocation
+/// takes effect everywhere at once, even for keys held in caches.
+///
+/// The file format has one revocation per line, made of the app UUID, the key fingerprint and
+/// optionally the Unix time the revocation is effective from, separated by whitespace. Either of
+/// the app UUID or the fingerprint can be `*` to match any. Blank lines and lines starting with
+/// `#` are ignored.
+///
+/// ```text
+/// # Every key of a decommissioned app
+/// 101c139a-236c-11ef-b5e3-125eb8485a60 *
+/// # A leaked key, from the time of the leak
+/// * 9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08 1767225600
+/// ```
+#[derive(Debug, Default)]
+pub struct RevocationList {
+    revocations: RwLock<Vec<Revocation>>,
+}
+
+impl RevocationList {
+    /// Initialize an empty revocation list
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read a revocation list file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let list = Self::new();
+        list.reload(path)?;
+
+        Ok(list)
+    }
+
+    /// Parse the contents of a revocation list file
+    ///
+    /// ```
+    /// # use mauth_core::error::Error;
+    /// # use mauth_core::keys::KeyFingerprint;
+    /// # use mauth_core::revocation::RevocationList;
+    /// # use std::time::SystemTime;
+    /// # let fingerprint: KeyFingerprint = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08".parse().unwrap();
+    /// let list = RevocationList::parse("101c139a-236c-11ef-b5e3-125eb8485a60 *").unwrap();
+    /// let result = list.check("101c139a-236c-11ef-b5e3-125eb8485a60", &fingerprint, SystemTime::now());
+    /// assert!(matches!(result, Err(Error::KeyRevoked { .. })));
+    /// ```
+    pub fn parse(contents: &str) -> Result<Self, Error> {
+        let list = Self::new();
+        list.replace(parse_revocations(contents)?);
+
+        Ok(list)
+    }
+
+    /// Read a revocation list file and replace the current revocations with its contents. The
+    /// current revocations are kept if the file cannot be read or parsed.
+    pub fn reload(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let revocations = parse_revocations(&std::fs::read_to_string(path)?)?;
+        self.replace(revocations);
+
+        Ok(())
+    }
+
+    /// Add a revocation to the list
+    pub fn revoke(&self, revocation: Revocation) {
+        self.write().push(revocation);
+    }
+
+    /// Replace all of the revocations in the list
+    pub fn replace(&self, revocations: Vec<Revocation>) {
+        *self.write() = revocations;
+    }
+
+    /// Check whether a key of an app has been revoked at the given time, returning
+    /// `Error::KeyRevoked` if it has.
+    pub fn check(
+        &self,
+        app_uuid: &str,
+        fingerprint: &KeyFingerprint,
+        now: SystemTime,
+    ) -> Result<(), Error> {
+        let revocations = self
+            .revocations
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        match revocations
+            .iter()
+            .any(|revocation| revocation.matches(app_uuid, fingerprint, now))
+        {
+            true => Err(Error::KeyRevoked {
+                app_uuid: app_uuid.to_owned(),
+                fingerprint: *fingerprint,
+            }),
+            false => Ok(()),
+        }
+    }
+
+    fn write(&self) -> std::sync::RwLockWriteGuard<'_, Vec<Revocation>> {
+        self.revocations
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+fn parse_revocations(contents: &str) -> Result<Vec<Revocation>, Error> {
+    contents
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(line_number, line)| {
+            parse_revocation(line).map_err(|reason| Error::RevocationListParseError {
+                line: line_number,
+                reason,
+            })
+        })
+        .collect()
+}
+
+fn parse_revocation(line: &str) -> Result<Revocation, String> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let (app_uuid, fingerprint, effective_at) = match fields[..] {
+        [app_uuid, fingerprint] => (app_uuid, fingerprint, None),
+        [app_uuid, fingerprint, effective_at] => (app_uuid, fingerprint, Some(effective_at)),
+        _ => return Err(format!("expected 2 or 3 fields, found {}", fields.len())),
+    };
+
+    let app_uuid = (app_uuid != "*").then(|| app_uuid.to_owned());
+    let fingerprint = match fingerprint {
+        "*" => None,
+        fingerprint => Some(fingerprint.parse().map_err(|e: Error| e.to_string())?),
+    };
+    if app_uuid.is_none() && fingerprint.is_none() {
+        return Err("the app UUID and fingerprint cannot both be `*`".to_owned());
+    }
+    let effective_at = match effective_at {
+        Some(seconds) => seconds
+            .parse()
+            .ok()
+            .and_then(|seconds| UNIX_EPOCH.checked_add(Duration::from_secs(seconds)))
+            .ok_or_else(|| format!("invalid effective time {seconds}"))?,
+        None => UNIX_EPOCH,
+    };
+
+    Ok(Revocation {
+        app_uuid,
+        fingerprint,
+        effective_at,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::tests::{signer, APP_UUID};
+
+    #[test]
+    fn check_matches_app_key_and_effective_time() {
+        let fingerprint = signer().fingerprint();
+        let now = SystemTime::now();
+        let hour = Duration::from_secs(3600);
+
+        let by_app = RevocationList::new();
+        by_app.revoke(Revocation::app(APP_UUID));
+        assert!(by_app.check(APP_UUID, &fingerprint, now).is_err());
+        assert!(by_app.check("other-app", &fingerprint, now).is_ok());
+
+        let by_key = RevocationList::new();
+        by_key.revoke(Revocation::key(fingerprint).for_app("other-app"));
+        assert!(by_key.check("other-app", &fingerprint, now).is_err());
+        assert!(by_key.check(APP_UUID, &fingerprint, now).is_ok());
+
+        let scheduled = RevocationList::new();
+        scheduled.revoke(Revocation::key(fingerprint).effective_at(now + hour));
+        assert!(scheduled.check(APP_UUID, &fingerprint, now).is_ok());
+        assert!(matches!(
+            scheduled.check(APP_UUID, &fingerprint, now + hour),
+            Err(Error::KeyRevoked { fingerprint: f, .. }) if f == fingerprint
+        ));
+    }
+
+    #[test]
+    fn parse_reads_each_line() {
+        let fingerprint = signer().fingerprint();
+        let list = RevocationList::parse(&format!(
+            "# comment\n\n{APP_UUID} *\n* {fingerprint} 1767225600\n"
+        ))
+        .unwrap();
+
+        assert_eq!(
+            *list.revocations.read().unwrap(),
+            [
+                Revocation::app(APP_UUID),
+                Revocation::key(fingerprint)
+                    .effective_at(UNIX_EPOCH + Duration::from_secs(1_767_225_600)),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_reports_the_invalid_line() {
+        for contents in [
+            "app *\n* *",
+            "app *\n* not-a-fingerprint",
+            "app *\napp * soon",
+            "app *\napp * 18446744073709551615",
+        ] {
+            assert!(matches!(
+                RevocationList::parse(contents),
+                Err(Error::RevocationListParseError { line: 2, .. })
+            ));
+        }
+    }
+}
@@ -10,6 +10,12 @@ pub static PERCENT_CASE_REGEX: Lazy<Regex> = lazy_regex!(r"%[a-f0-9]{2}");
 pub static SINGLE_DOT_REGEX: Lazy<Regex> = lazy_regex!(r"/(\./|\.\z)+");
 pub static DOUBLE_DOT_REGEX: Lazy<Regex> = lazy_regex!(r"(/[^/]+|\A)/\.\./?");
 
+/// Something that can be signed with either version of the MAuth protocol
+pub(crate) trait SigningString {
+    fn signing_string_v1(&self) -> Result<Vec<u8>, Error>;
+    fn signing_string_v2(&self) -> Result<Vec<u8>, Error>;
+}
+
 pub(crate) struct Signable<'a> {
     verb: String,
     path: String,
@@ -38,38 +44,6 @@ impl<'a> Signable<'a> {
         }
     }
 
-    pub fn signing_string_v1(&self) -> Result<Vec<u8>, Error> {
-        let mut hasher = Sha512::default();
-
-        hasher.update(&self.verb);
-        hasher.update("\n");
-        hasher.update(&self.path);
-        hasher.update("\n");
-        hasher.update(self.body);
-        hasher.update("\n");
-        hasher.update(&self.app_uuid);
-        hasher.update("\n");
-        hasher.update(&self.timestamp);
-
-        Ok(hex::encode(hasher.finalize()).into_bytes())
-    }
-
-    pub fn signing_string_v2(&self) -> Result<Vec<u8>, Error> {
-        let encoded_query: String = Self::encode_query(&self.query)?;
-        let body_digest = hex::encode(Sha512::digest(self.body));
-
-        Ok(format!(
-            "{}\n{}\n{}\n{}\n{}\n{}",
-            self.verb,
-            Self::normalize_url(&self.path),
-            body_digest,
-            self.app_uuid,
-            self.timestamp,
-            encoded_query
-        )
-        .into_bytes())
-    }
-
     fn encode_query(qstr: &str) -> Result<String, Error> {
         if qstr.is_empty() {
             return Ok("".to_string());
@@ -122,6 +96,170 @@ impl<'a> Signable<'a> {
     }
 }
 
+impl SigningString for Signable<'_> {
+    fn signing_string_v1(&self) -> Result<Vec<u8>, Error> {
+        let mut hasher = Sha512::default();
+
+        hasher.update(&self.verb);
+        hasher.update("\n");
+        hasher.update(&self.path);
+        hasher.update("\n");
+        hasher.update(self.body);
+        hasher.update("\n");
+        hasher.update(&self.app_uuid);
+        hasher.update("\n");
+        hasher.update(&self.timestamp);
+
+        Ok(hex::encode(hasher.finalize()).into_bytes())
+    }
+
+    fn signing_string_v2(&self) -> Result<Vec<u8>, Error> {
+        let encoded_query: String = Self::encode_query(&self.query)?;
+        let body_digest = hex::encode(Sha512::digest(self.body));
+
+        Ok(format!(
+            "{}\n{}\n{}\n{}\n{}\n{}",
+            self.verb,
+            Self::normalize_url(&self.path),
+            body_digest,
+            self.app_uuid,
+            self.timestamp,
+            encoded_query
+        )
+        .into_bytes())
+    }
+}
+
+/// A response, which is signed with its status code in place of the verb, path and query
+pub(crate) struct SignableResponse<'a> {
+    status: u16,
+    body: &'a [u8],
+    timestamp: String,
+    app_uuid: String,
+}
+
+impl<'a> SignableResponse<'a> {
+    pub fn new(
+        status: u16,
+        body: &'a [u8],
+        timestamp: impl Into<String>,
+        app_uuid: impl Into<String>,
+    ) -> Self {
+        Self {
+            status,
+            body,
+            timestamp: timestamp.into(),
+            app_uuid: app_uuid.into(),
+        }
+    }
+}
+
+impl SigningString for SignableResponse<'_> {
+    fn signing_string_v1(&self) -> Result<Vec<u8>, Error> {
+        let mut hasher = Sha512::default();
+
+        hasher.update(self.status.to_string());
+        hasher.update("\n");
+        hasher.update(self.body);
+        hasher.update("\n");
+        hasher.update(&self.app_uuid);
+        hasher.update("\n");
+        hasher.update(&self.timestamp);
+
+        Ok(hex::encode(hasher.finalize()).into_bytes())
+    }
+
+    fn signing_string_v2(&self) -> Result<Vec<u8>, Error> {
+        let body_digest = hex::encode(Sha512::digest(self.body));
+
+        Ok(format!(
+            "{}\n{}\n{}\n{}",
+            self.status, body_digest, self.app_uuid, self.timestamp
+        )
+        .into_bytes())
+    }
+}
+
+/// A message published to a queue or event stream, which is signed with its topic in place of a
+/// request or status line. Messages are only signed with the V2 machinery, and their signing
+/// string starts with a line of its own so that a message signature is never valid for a request
+/// or response.
+pub(crate) struct SignableMessage<'a> {
+    topic: String,
+    payload: &'a [u8],
+    timestamp: String,
+    app_uuid: String,
+}
+
+impl<'a> SignableMessage<'a> {
+    pub fn new(
+        topic: impl Into<String>,
+        payload: &'a [u8],
+        timestamp: impl Into<String>,
+        app_uuid: impl Into<String>,
+    ) -> Self {
+        Self {
+            topic: topic.into(),
+            payload,
+            timestamp: timestamp.into(),
+            app_uuid: app_uuid.into(),
+        }
+    }
+}
+
+impl SigningString for SignableMessage<'_> {
+    fn signing_string_v1(&self) -> Result<Vec<u8>, Error> {
+        Err(Error::UnsupportedVersion(1))
+    }
+
+    fn signing_string_v2(&self) -> Result<Vec<u8>, Error> {
+        let payload_digest = hex::encode(Sha512::digest(self.payload));
+
+        Ok(format!(
+            "MWSMSG\n{}\n{}\n{}\n{}",
+            self.topic, payload_digest, self.app_uuid, self.timestamp
+        )
+        .into_bytes())
+    }
+}
+
+/// A file or artifact, signed by the SHA-512 digest of its contents so that it can be read in
+/// chunks. Like messages, artifacts are only signed with the V2 machinery and have a first line of
+/// their own.
+pub(crate) struct SignableArtifact {
+    digest: String,
+    timestamp: String,
+    app_uuid: String,
+}
+
+impl SignableArtifact {
+    pub fn new(
+        digest: impl Into<String>,
+        timestamp: impl Into<String>,
+        app_uuid: impl Into<String>,
+    ) -> Self {
+        Self {
+            digest: digest.into(),
+            timestamp: timestamp.into(),
+            app_uuid: app_uuid.into(),
+        }
+    }
+}
+
+impl SigningString for SignableArtifact {
+    fn signing_string_v1(&self) -> Result<Vec<u8>, Error> {
+        Err(Error::UnsupportedVersion(1))
+    }
+
+    fn signing_string_v2(&self) -> Result<Vec<u8>, Error> {
+        Ok(format!(
+            "MWSFILE\n{}\n{}\n{}",
+            self.digest, self.app_uuid, self.timestamp
+        )
+        .into_bytes())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -164,4 +302,26 @@ mod tests {
     fn normalize_url_test(url: &str, expected: &str) {
         assert_eq!(Signable::normalize_url(url), expected);
     }
+
+    #[test]
+    fn response_signing_string_v2_includes_status_and_body_digest() {
+        let signable = SignableResponse::new(201, b"", "1669858655", "app");
+
+        assert_eq!(
+            String::from_utf8(signable.signing_string_v2().unwrap()).unwrap(),
+            format!("201\n{}\napp\n1669858655", hex::encode(Sha512::digest(b"")))
+        );
+    }
+
+    #[test]
+    fn message_signing_string_is_distinct_from_responses() {
+        let message = SignableMessage::new("200", b"", "1669858655", "app");
+        let response = SignableResponse::new(200, b"", "1669858655", "app");
+
+        assert!(message.signing_string_v1().is_err());
+        assert_ne!(
+            message.signing_string_v2().unwrap(),
+            response.signing_string_v2().unwrap()
+        );
+    }
 }
@@ -1,16 +1,35 @@
+use crate::keys::{KeyFingerprint, KeyPolicy};
 use crate::pem_format;
-use crate::{error::Error, signable::Signable};
+use crate::signable::{Signable, SignableMessage, SignableResponse, SigningString};
+use crate::{error::Error, verifier::Verifier};
 use base64::{engine::general_purpose, Engine as _};
 use rsa::pkcs1::DecodeRsaPrivateKey;
 use rsa::RsaPrivateKey;
 use sha2::Sha512;
+use std::fmt;
+use std::sync::{Arc, PoisonError, RwLock};
+use std::time::SystemTime;
+use zeroize::Zeroizing;
 
 /// Used to sign outgoing requests. Struct can be initialized once and used to sign many requests.
-#[derive(Debug, Clone)]
+///
+/// The private key is held in a single allocation that is shared between clones and zeroized when
+/// the last clone is dropped. The `Debug` output only contains the app UUID and the fingerprint of
+/// the key, never the key material.
+#[derive(Clone)]
 pub struct Signer {
     app_uuid: String,
-    private_key: RsaPrivateKey,
-    signing_key: rsa::pkcs1v15::SigningKey<Sha512>,
+    fingerprint: KeyFingerprint,
+    signing_key: Arc<rsa::pkcs1v15::SigningKey<Sha512>>,
+}
+
+impl fmt::Debug for Signer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Signer")
+            .field("app_uuid", &self.app_uuid)
+            .field("fingerprint", &self.fingerprint)
+            .finish_non_exhaustive()
+    }
 }
 
 impl Signer {
@@ -19,7 +38,8 @@ impl Signer {
     /// error will be returned if the input data is unable to be parsed as a private key. The `app_uuid`
     /// is expected to be a valid UUID, however this is not checked. If you pass something other than
     /// a valid UUID, no error will be returned, but none of the created signatures will be able to
-    /// be validated by other MAuth verifiers.
+    /// be validated by other MAuth verifiers. The key must meet the default `KeyPolicy`, use
+    /// `Signer::new_with_policy` to accept weaker legacy keys.
     ///
     /// ```
     /// # use mauth_core::signer::Signer;
@@ -28,18 +48,69 @@ impl Signer {
     /// assert!(signer.is_ok());
     /// ```
     pub fn new(app_uuid: impl Into<String>, private_key_data: String) -> Result<Self, Error> {
+        Self::new_with_policy(app_uuid, private_key_data, &KeyPolicy::default())
+    }
+
+    /// Initialize a new signer in the same way as `Signer::new`, but checking the private key
+    /// against the provided `KeyPolicy` instead of the default one.
+    ///
+    /// ```
+    /// # use mauth_core::keys::KeyPolicy;
+    /// # use mauth_core::signer::Signer;
+    /// # let private_key = std::fs::read_to_string("tests/mauth-protocol-test-suite/signing-params/rsa-key").unwrap();
+    /// let signer = Signer::new_with_policy("101c139a-236c-11ef-b5e3-125eb8485a60", private_key, &KeyPolicy::legacy());
+    /// assert!(signer.is_ok());
+    /// ```
+    pub fn new_with_policy(
+        app_uuid: impl Into<String>,
+        private_key_data: String,
+        policy: &KeyPolicy,
+    ) -> Result<Self, Error> {
+        let private_key_data = Zeroizing::new(private_key_data);
         let private_key = RsaPrivateKey::from_pkcs1_pem(&pem_format::normalize_rsa_private_key(
-            private_key_data,
+            &private_key_data,
         ))?;
-        let signing_key = rsa::pkcs1v15::SigningKey::<Sha512>::new(private_key.to_owned());
+        policy.check(&private_key)?;
+        let fingerprint = KeyFingerprint::from_public_key(private_key.as_ref())?;
+        let signing_key = rsa::pkcs1v15::SigningKey::<Sha512>::new(private_key);
 
         Ok(Self {
             app_uuid: app_uuid.into(),
-            private_key,
-            signing_key,
+            fingerprint,
+            signing_key: Arc::new(signing_key),
         })
     }
 
+    /// Build a `Verifier` for the public half of this signer's key, using the same app UUID. This
+    /// is useful for checking signatures produced by this signer, e.g. in tests.
+    ///
+    /// ```
+    /// # use mauth_core::signer::Signer;
+    /// # let private_key = std::fs::read_to_string("tests/mauth-protocol-test-suite/signing-params/rsa-key").unwrap();
+    /// # let signer = Signer::new("101c139a-236c-11ef-b5e3-125eb8485a60", private_key).unwrap();
+    /// let signature = signer.sign_string(2, "GET", "/item", "page=2", b"", "2024-01-28T19:11:35.000").unwrap();
+    /// let verifier = signer.verifier();
+    /// let result = verifier.verify_signature(2, "GET", "/item", "page=2", b"", "2024-01-28T19:11:35.000", signature);
+    /// assert!(result.is_ok());
+    /// ```
+    pub fn verifier(&self) -> Verifier {
+        Verifier::from_public_key(
+            &self.app_uuid,
+            self.private_key().to_public_key(),
+            self.fingerprint,
+        )
+    }
+
+    /// The fingerprint of the key used to create signatures
+    pub fn fingerprint(&self) -> KeyFingerprint {
+        self.fingerprint
+    }
+
+    /// The app UUID that signatures are created for
+    pub fn app_uuid(&self) -> &str {
+        &self.app_uuid
+    }
+
     /// This function will generate a valid MAuth signature string of the specified version, or error
     /// if it is unable to.
     ///
@@ -61,25 +132,368 @@ impl Signer {
     ) -> Result<String, Error> {
         let signable = Signable::new(verb, path, query, body, timestamp, &self.app_uuid);
 
+        self.sign_signable(version, &signable)
+    }
+
+    /// Generate a MAuth signature string of the specified version for a response with the given
+    /// status code and body, so that the client can check that it came from this app.
+    ///
+    /// ```
+    /// # use mauth_core::keys::{KeyPair, KeySize, PrivateKeyFormat};
+    /// # use mauth_core::signer::Signer;
+    /// # let signer = Signer::new("101c139a-236c-11ef-b5e3-125eb8485a60", KeyPair::generate(KeySize::Rsa2048).unwrap().private_key_pem(PrivateKeyFormat::Pkcs1).unwrap().to_string()).unwrap();
+    /// let signature = signer.sign_response(2, 200, b"{}", "1669858655").unwrap();
+    /// let result = signer.verifier().verify_response_signature(2, 200, b"{}", "1669858655", signature);
+    /// assert!(result.is_ok());
+    /// ```
+    pub fn sign_response(
+        &self,
+        version: u8,
+        status: u16,
+        body: &[u8],
+        timestamp: impl Into<String>,
+    ) -> Result<String, Error> {
+        let signable = SignableResponse::new(status, body, timestamp, &self.app_uuid);
+
+        self.sign_signable(version, &signable)
+    }
+
+    /// Generate a MAuth signature string for a message published to `topic`, such as a Kafka
+    /// topic or a RabbitMQ routing key, so that consumers can check which app produced it.
+    /// Messages are always signed with the V2 algorithm.
+    ///
+    /// ```
+    /// # use mauth_core::keys::{KeyPair, KeySize, PrivateKeyFormat};
+    /// # use mauth_core::signer::Signer;
+    /// # let signer = Signer::new("101c139a-236c-11ef-b5e3-125eb8485a60", KeyPair::generate(KeySize::Rsa2048).unwrap().private_key_pem(PrivateKeyFormat::Pkcs1).unwrap().to_string()).unwrap();
+    /// let signature = signer.sign_message("orders.created", b"{}", "1669858655").unwrap();
+    /// let result = signer.verifier().verify_message_signature("orders.created", b"{}", "1669858655", signature);
+    /// assert!(result.is_ok());
+    /// ```
+    pub fn sign_message(
+        &self,
+        topic: impl Into<String>,
+        payload: &[u8],
+        timestamp: impl Into<String>,
+    ) -> Result<String, Error> {
+        let signable = SignableMessage::new(topic, payload, timestamp, &self.app_uuid);
+
+        self.sign_signable(2, &signable)
+    }
+
+    pub(crate) fn sign_signable(
+        &self,
+        version: u8,
+        signable: &impl SigningString,
+    ) -> Result<String, Error> {
         match version {
-            1 => self.sign_string_v1(&signable),
-            2 => self.sign_string_v2(&signable),
+            1 => self.sign_string_v1(signable),
+            2 => self.sign_string_v2(signable),
             v => Err(Error::UnsupportedVersion(v)),
         }
     }
 
-    fn sign_string_v1(&self, signable: &Signable) -> Result<String, Error> {
-        let signature = self.private_key.sign(
+    fn private_key(&self) -> &RsaPrivateKey {
+        (*self.signing_key).as_ref()
+    }
+
+    fn sign_string_v1(&self, signable: &impl SigningString) -> Result<String, Error> {
+        let signature = self.private_key().sign(
             rsa::Pkcs1v15Sign::new_unprefixed(),
             &signable.signing_string_v1()?,
         )?;
         Ok(general_purpose::STANDARD.encode(signature))
     }
 
-    fn sign_string_v2(&self, signable: &Signable) -> Result<String, Error> {
+    fn sign_string_v2(&self, signable: &impl SigningString) -> Result<String, Error> {
         use rsa::signature::{SignatureEncoding, Signer};
 
         let sign = self.signing_key.sign(&signable.signing_string_v2()?);
         Ok(general_purpose::STANDARD.encode(sign.to_bytes().as_ref()))
     }
 }
+
+/// A signature string along with the fingerprint of the key that created it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyedSignature {
+    /// The MAuth signature string
+    pub signature: String,
+    /// The fingerprint of the key that created the signature
+    pub fingerprint: KeyFingerprint,
+}
+
+/// Used to sign outgoing requests for an app that rotates its private key. It holds the current
+/// signer and optionally a staged signer with an activation time. Once the activation time has
+/// passed, the staged signer atomically replaces the current one, so processes do not need to be
+/// restarted at the cutover moment.
+#[derive(Debug)]
+pub struct RotatingSigner {
+    app_uuid: String,
+    state: RwLock<RotationState>,
+}
+
+#[derive(Debug)]
+struct RotationState {
+    current: Signer,
+    staged: Option<(Signer, SystemTime)>,
+}
+
+impl RotatingSigner {
+    /// Initialize a new rotating signer with the signer that is currently in use
+    pub fn new(current: Signer) -> Self {
+        Self {
+            app_uuid: current.app_uuid.clone(),
+            state: RwLock::new(RotationState {
+                current,
+                staged: None,
+            }),
+        }
+    }
+
+    /// Stage the next signer, to be used from the given activation time onwards. This replaces
+    /// any signer that was previously staged. An error is returned if the signer was created for a
+    /// different app UUID.
+    ///
+    /// ```
+    /// # use mauth_core::keys::{KeyPair, KeySize, PrivateKeyFormat};
+    /// # use mauth_core::signer::{RotatingSigner, Signer};
+    /// # use std::time::SystemTime;
+    /// # let new_signer = || Signer::new("101c139a-236c-11ef-b5e3-125eb8485a60", KeyPair::generate(KeySize::Rsa2048).unwrap().private_key_pem(PrivateKeyFormat::Pkcs1).unwrap().to_string()).unwrap();
+    /// # let (current, next) = (new_signer(), new_signer());
+    /// let signer = RotatingSigner::new(current);
+    /// signer.stage(next.clone(), SystemTime::now()).unwrap();
+    ///
+    /// let result = signer.sign_string(2, "GET", "/item", "page=2", b"", "1669858655").unwrap();
+    /// assert_eq!(result.fingerprint, next.fingerprint());
+    /// ```
+    pub fn stage(&self, next: Signer, activate_at: SystemTime) -> Result<(), Error> {
+        if next.app_uuid != self.app_uuid {
+            return Err(Error::AppUuidMismatch {
+                expected: self.app_uuid.clone(),
+                actual: next.app_uuid,
+            });
+        }
+        self.write_state().staged = Some((next, activate_at));
+
+        Ok(())
+    }
+
+    /// The fingerprint and activation time of the staged signer, if there is one
+    pub fn staged(&self) -> Option<(KeyFingerprint, SystemTime)> {
+        let state = self.state.read().unwrap_or_else(PoisonError::into_inner);
+        state
+            .staged
+            .as_ref()
+            .map(|(signer, activate_at)| (signer.fingerprint, *activate_at))
+    }
+
+    /// The signer in use at the current time, activating the staged signer if it is due. When
+    /// creating both V1 and V2 signatures for a request, sign with the returned signer so both are
+    /// made with the same key even if a rotation happens in between.
+    pub fn current(&self) -> Signer {
+        let now = SystemTime::now();
+        {
+            let state = self.state.read().unwrap_or_else(PoisonError::into_inner);
+            if !Self::is_due(&state, now) {
+                return state.current.clone();
+            }
+        }
+
+        let mut state = self.write_state();
+        if Self::is_due(&state, now) {
+            if let Some((next, _)) = state.staged.take() {
+                state.current = next;
+            }
+        }
+        state.current.clone()
+    }
+
+    /// Generate a MAuth signature string with the current signer in the same way as
+    /// `Signer::sign_string`, returning it along with the fingerprint of the key that was used.
+    pub fn sign_string(
+        &self,
+        version: u8,
+        verb: impl Into<String>,
+        path: impl Into<String>,
+        query: impl Into<String>,
+        body: &[u8],
+        timestamp: impl Into<String>,
+    ) -> Result<KeyedSignature, Error> {
+        let signer = self.current();
+        let signature = signer.sign_string(version, verb, path, query, body, timestamp)?;
+
+        Ok(KeyedSignature {
+            signature,
+            fingerprint: signer.fingerprint,
+        })
+    }
+
+    fn is_due(state: &RotationState, now: SystemTime) -> bool {
+        matches!(state.staged, Some((_, activate_at)) if activate_at <= now)
+    }
+
+    fn write_state(&self) -> std::sync::RwLockWriteGuard<'_, RotationState> {
+        self.state.write().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::tests::{key_pair, next_signer, signer};
+    use crate::keys::{KeyPolicyViolation, PrivateKeyFormat};
+    use rsa::pkcs1::{EncodeRsaPrivateKey, LineEnding};
+
+    #[test]
+    fn new_applies_key_policy() {
+        let private_key = RsaPrivateKey::new(&mut rsa::rand_core::OsRng, 1024).unwrap();
+        let private_key_pem = private_key
+            .to_pkcs1_pem(LineEnding::LF)
+            .unwrap()
+            .to_string();
+
+        assert!(matches!(
+            Signer::new("app", private_key_pem.clone()),
+            Err(Error::KeyPolicyViolation(
+                KeyPolicyViolation::ModulusTooSmall { .. }
+            ))
+        ));
+        assert!(Signer::new_with_policy("app", private_key_pem, &KeyPolicy::legacy()).is_ok());
+    }
+
+    #[test]
+    fn debug_redacts_private_key() {
+        let signer = signer();
+        let debug = format!("{signer:?}");
+
+        assert_eq!(
+            debug,
+            format!(
+                "Signer {{ app_uuid: \"{}\", fingerprint: KeyFingerprint({}), .. }}",
+                signer.app_uuid, signer.fingerprint
+            )
+        );
+    }
+
+    #[test]
+    fn clones_share_private_key() {
+        let signer = signer();
+        let clone = signer.clone();
+
+        assert!(Arc::ptr_eq(&signer.signing_key, &clone.signing_key));
+    }
+
+    #[test]
+    fn rotating_signer_switches_to_staged_signer_once_due() {
+        let current = signer();
+        let next = next_signer();
+        let rotating = RotatingSigner::new(current.clone());
+        let hour = std::time::Duration::from_secs(3600);
+
+        rotating
+            .stage(next.clone(), SystemTime::now() + hour)
+            .unwrap();
+        let result = rotating
+            .sign_string(2, "GET", "/", "", b"", "1669858655")
+            .unwrap();
+        assert_eq!(result.fingerprint, current.fingerprint);
+        assert!(current
+            .verifier()
+            .verify_signature(2, "GET", "/", "", b"", "1669858655", result.signature)
+            .is_ok());
+        assert_eq!(rotating.staged().unwrap().0, next.fingerprint);
+
+        rotating
+            .stage(next.clone(), SystemTime::now() - hour)
+            .unwrap();
+        let result = rotating
+            .sign_string(2, "GET", "/", "", b"", "1669858655")
+            .unwrap();
+        assert_eq!(result.fingerprint, next.fingerprint);
+        assert!(rotating.staged().is_none());
+    }
+
+    #[test]
+    fn rotating_signer_rejects_signers_for_other_apps() {
+        let rotating = RotatingSigner::new(signer());
+        let private_key = key_pair().private_key_pem(PrivateKeyFormat::Pkcs1).unwrap();
+        let other = Signer::new("another-app", private_key.to_string()).unwrap();
+
+        assert!(matches!(
+            rotating.stage(other, SystemTime::now()),
+            Err(Error::AppUuidMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn verifier_checks_signatures_from_the_same_key() {
+        let signer = signer();
+        let verifier = signer.verifier();
+
+        for version in [1, 2] {
+            let signature = signer
+                .sign_string(version, "PUT", "/item", "", b"body", "1669858655")
+                .unwrap();
+            assert!(verifier
+                .verify_signature(
+                    version,
+                    "PUT",
+                    "/item",
+                    "",
+                    b"body",
+                    "1669858655",
+                    &signature
+                )
+                .is_ok());
+            assert!(verifier
+                .verify_signature(
+                    version,
+                    "PUT",
+                    "/item",
+                    "",
+                    b"other",
+                    "1669858655",
+                    &signature
+                )
+                .is_err());
+        }
+    }
+
+    #[test]
+    fn response_signatures_only_verify_as_responses() {
+        let signer = signer();
+        let verifier = signer.verifier();
+
+        for version in [1, 2] {
+            let signature = signer
+                .sign_response(version, 200, b"body", "1669858655")
+                .unwrap();
+            assert!(verifier
+                .verify_response_signature(version, 200, b"body", "1669858655", &signature)
+                .is_ok());
+            assert!(verifier
+                .verify_response_signature(version, 500, b"body", "1669858655", &signature)
+                .is_err());
+            assert!(verifier
+                .verify_signature(version, "200", "", "", b"body", "1669858655", &signature)
+                .is_err());
+        }
+    }
+
+    #[test]
+    fn message_signatures_only_verify_on_their_topic() {
+        let signer = signer();
+        let verifier = signer.verifier();
+        let signature = signer.sign_message("orders", b"{}", "1669858655").unwrap();
+
+        assert!(verifier
+            .verify_message_signature("orders", b"{}", "1669858655", &signature)
+            .is_ok());
+        assert!(verifier
+            .verify_message_signature("payments", b"{}", "1669858655", &signature)
+            .is_err());
+        assert!(verifier
+            .verify_signature(2, "orders", "", "", b"{}", "1669858655", &signature)
+            .is_err());
+    }
+}
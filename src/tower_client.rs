@@ -0,0 +1,343 @@
+use crate::error::Error;
+use crate::http::{
+    signature_headers, HostAllowlist, SigningVersions, DEFAULT_BODY_LIMIT, MAUTH_HEADERS,
+};
+use crate::registry::SignerSelector;
+use crate::tower_server::{read_body, request_host};
+use bytes::Bytes;
+use http::{Request, Response, Uri};
+use http_body::Body;
+use http_body_util::Full;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tower_layer::Layer;
+use tower_service::Service;
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
+
+/// A tower layer that signs outgoing `http::Request`s with MAuth, for hyper-util clients and other
+/// tower based HTTP clients.
+///
+/// The body has to be hashed before the headers can be sent, so it is buffered, up to a limit, and
+/// the inner service receives it as a `Full<Bytes>` body. The signer is chosen for each request by
+/// a `registry::SignerSelector` from the host and path of the request, taking the host from the
+/// URI or, if the URI has none, from the `Host` header. Any MAuth headers already on a request are
+/// replaced. Every request is signed unless a `http::HostAllowlist` is set, which should be done
+/// for clients that may send requests to third parties.
+///
+/// Errors from the inner service are boxed. Requests that cannot be signed fail with an
+/// `error::Error`, such as `Error::BodyTooLarge` or `Error::NoSignerSelected`.
+///
+/// ```
+/// # use mauth_core::keys::{KeyPair, KeySize, PrivateKeyFormat};
+/// # use mauth_core::registry::SignerRegistry;
+/// # use mauth_core::signer::Signer;
+/// # use mauth_core::tower_client::SigningLayer;
+/// # let new_signer = |app_uuid| Signer::new(app_uuid, KeyPair::generate(KeySize::Rsa2048).unwrap().private_key_pem(PrivateKeyFormat::Pkcs1).unwrap().to_string()).unwrap();
+/// let mut registry = SignerRegistry::new();
+/// registry.insert(new_signer("101c139a-236c-11ef-b5e3-125eb8485a60"));
+/// registry.set_default("101c139a-236c-11ef-b5e3-125eb8485a60").unwrap();
+///
+/// let layer = SigningLayer::new(registry);
+/// ```
+#[derive(Clone)]
+pub struct SigningLayer {
+    selector: Arc<dyn SignerSelector>,
+    versions: SigningVersions,
+    body_limit: usize,
+    allowlist: Option<Arc<HostAllowlist>>,
+}
+
+impl fmt::Debug for SigningLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SigningLayer")
+            .field("versions", &self.versions)
+            .field("body_limit", &self.body_limit)
+            .field("allowlist", &self.allowlist)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SigningLayer {
+    /// Initialize a layer that signs requests with the signer chosen by `selector` using V2,
+    /// buffering bodies of up to `http::DEFAULT_BODY_LIMIT` bytes.
+    pub fn new(selector: impl SignerSelector + 'static) -> Self {
+        Self {
+            selector: Arc::new(selector),
+            versions: SigningVersions::default(),
+            body_limit: DEFAULT_BODY_LIMIT,
+            allowlist: None,
+        }
+    }
+
+    /// Set the MAuth protocol versions requests are signed with
+    pub fn with_versions(mut self, versions: SigningVersions) -> Self {
+        self.versions = versions;
+        self
+    }
+
+    /// Set the largest request body, in bytes, that will be buffered for signing
+    pub fn with_body_limit(mut self, body_limit: usize) -> Self {
+        self.body_limit = body_limit;
+        self
+    }
+
+    /// Only sign requests to the hosts on `allowlist`
+    pub fn with_allowlist(mut self, allowlist: HostAllowlist) -> Self {
+        self.allowlist = Some(Arc::new(allowlist));
+        self
+    }
+}
+
+impl<S> Layer<S> for SigningLayer {
+    type Service = SigningService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        SigningService {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// The service created by `SigningLayer`
+#[derive(Debug, Clone)]
+pub struct SigningService<S> {
+    inner: S,
+    layer: SigningLayer,
+}
+
+impl<S, B, ResBody> Service<Request<B>> for SigningService<S>
+where
+    S: Service<Request<Full<Bytes>>, Response = Response<ResBody>> + Clone + Send + 'static,
+    S::Error: Into<BoxError>,
+    S::Future: Send,
+    B: Body + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+{
+    type Response = Response<ResBody>;
+    type Error = BoxError;
+    type Future = BoxFuture<Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        // The clone is not guaranteed to be ready, so the ready service is taken for this call
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let layer = self.layer.clone();
+
+        Box::pin(async move {
+            let (parts, body) = layer.buffer_and_sign(request).await?;
+
+            inner
+                .call(Request::from_parts(parts, Full::new(body)))
+                .await
+                .map_err(Into::into)
+        })
+    }
+}
+
+impl SigningLayer {
+    /// Buffer the body of a request and sign it, returning its parts and body to send on
+    pub(crate) async fn buffer_and_sign<B>(
+        &self,
+        request: Request<B>,
+    ) -> Result<(http::request::Parts, Bytes), Error>
+    where
+        B: Body,
+        B::Error: Into<BoxError>,
+    {
+        let (mut parts, body) = request.into_parts();
+        let body = read_body(body, self.body_limit).await?;
+        self.sign(&mut parts, &body)?;
+
+        Ok((parts, body))
+    }
+
+    fn sign(&self, parts: &mut http::request::Parts, body: &[u8]) -> Result<(), Error> {
+        let uri = &parts.uri;
+        let host = request_host(uri, &parts.headers);
+        let origin = || origin(uri, host.as_deref());
+        if let Some(allowlist) = &self.allowlist {
+            if !allowlist.should_sign(uri.scheme_str(), host.as_deref(), origin)? {
+                for name in MAUTH_HEADERS {
+                    parts.headers.remove(name);
+                }
+                return Ok(());
+            }
+        }
+        let signer = self
+            .selector
+            .select_signer(host.as_deref(), uri.path())
+            .ok_or_else(|| Error::NoSignerSelected(origin()))?;
+        let headers = signature_headers(
+            &signer,
+            self.versions,
+            parts.method.as_str(),
+            uri.path(),
+            uri.query().unwrap_or_default(),
+            body,
+        )?;
+
+        for (name, value) in headers {
+            parts.headers.insert(name, value);
+        }
+
+        Ok(())
+    }
+}
+
+/// The scheme and authority of `uri`, or the `host` it was sent to, leaving out the path and query
+/// from error messages
+fn origin(uri: &Uri, host: Option<&str>) -> String {
+    match (uri.scheme(), uri.authority(), host) {
+        (Some(scheme), Some(authority), _) => format!("{scheme}://{authority}"),
+        (_, Some(authority), _) => authority.to_string(),
+        (_, None, Some(host)) => host.to_owned(),
+        (_, None, None) => uri.path().to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::tests::authenticator;
+    use crate::http::{AuthenticatedApp, RequestAuthenticator, UnlistedHosts, MCC_AUTHENTICATION};
+    use crate::keys::tests::{key_pair, APP_UUID};
+    use crate::keys::PrivateKeyFormat;
+    use crate::registry::SignerRegistry;
+    use crate::signer::Signer;
+    use http_body_util::BodyExt;
+    use tower::{service_fn, ServiceExt};
+
+    fn registry() -> SignerRegistry {
+        let private_key = key_pair().private_key_pem(PrivateKeyFormat::Pkcs1).unwrap();
+        let mut registry = SignerRegistry::new();
+        registry.insert(Signer::new(APP_UUID, private_key.to_string()).unwrap());
+        registry.insert(Signer::new("other-app", private_key.to_string()).unwrap());
+        registry.route_host("api.example.com", APP_UUID).unwrap();
+        registry
+    }
+
+    /// A client that authenticates the requests it is sent as the server would
+    fn client(
+        layer: SigningLayer,
+    ) -> impl Service<Request<Full<Bytes>>, Response = Response<Full<Bytes>>, Error = BoxError>
+    {
+        let authenticator = RequestAuthenticator::new(authenticator());
+        layer.layer(service_fn(move |request: Request<Full<Bytes>>| {
+            let authenticator = authenticator.clone();
+            async move {
+                let (parts, body) = request.into_parts();
+                let body = body.collect().await?.to_bytes();
+                let AuthenticatedApp { app_uuid, .. } =
+                    authenticator.authenticate_request(&parts, &body)?;
+
+                Ok::<_, BoxError>(Response::new(Full::new(Bytes::from(app_uuid))))
+            }
+        }))
+    }
+
+    #[tokio::test]
+    async fn requests_are_signed_by_the_selected_signer() {
+        let request = Request::post("https://api.example.com/items?page=2")
+            .header(MCC_AUTHENTICATION, "MWSV2 stale:signature;")
+            .body(Full::new(Bytes::from_static(b"{}")))
+            .unwrap();
+
+        let response = client(SigningLayer::new(registry()))
+            .oneshot(request)
+            .await
+            .unwrap();
+
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, APP_UUID);
+    }
+
+    #[tokio::test]
+    async fn origin_form_requests_use_the_host_header() {
+        let request = |host| {
+            Request::get("/items")
+                .header(http::header::HOST, host)
+                .body(Full::default())
+                .unwrap()
+        };
+        let mut allowlist = HostAllowlist::new(UnlistedHosts::Refuse);
+        allowlist.allow("api.example.com").unwrap();
+        let layer = SigningLayer::new(registry()).with_allowlist(allowlist);
+
+        let response = client(layer.clone())
+            .oneshot(request("api.example.com:8443"))
+            .await
+            .unwrap();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, APP_UUID);
+
+        let refused = client(layer)
+            .oneshot(request("elsewhere.example.com"))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            refused.downcast_ref::<Error>(),
+            Some(Error::HostNotAllowed(origin)) if origin == "elsewhere.example.com"
+        ));
+    }
+
+    #[tokio::test]
+    async fn requests_without_a_signer_fail() {
+        let request = Request::get("https://elsewhere.example.com/")
+            .body(Full::default())
+            .unwrap();
+
+        let error = client(SigningLayer::new(registry()))
+            .oneshot(request)
+            .await
+            .unwrap_err();
+
+        assert!(matches!(
+            error.downcast_ref::<Error>(),
+            Some(Error::NoSignerSelected(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn requests_to_unlisted_hosts_are_refused_or_sent_unsigned() {
+        let request = || {
+            Request::get("http://api.example.com/items")
+                .header(MCC_AUTHENTICATION, "MWSV2 stale:signature;")
+                .body(Full::default())
+                .unwrap()
+        };
+        let layer = |unlisted| {
+            let mut allowlist = HostAllowlist::new(unlisted);
+            allowlist.allow("https://api.example.com").unwrap();
+            SigningLayer::new(registry()).with_allowlist(allowlist)
+        };
+
+        let refused = client(layer(UnlistedHosts::Refuse))
+            .oneshot(request())
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            refused.downcast_ref::<Error>(),
+            Some(Error::HostNotAllowed(origin)) if origin == "http://api.example.com"
+        ));
+
+        let unsigned = client(layer(UnlistedHosts::SendUnsigned))
+            .oneshot(request())
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            unsigned.downcast_ref::<Error>(),
+            Some(Error::MissingAuthentication)
+        ));
+    }
+}
@@ -0,0 +1,618 @@
+use crate::error::Error;
+use crate::http::{
+    rejection_status, response_signature_headers, AuthenticatedApp, MAuthHeaders,
+    RequestAuthenticator, SigningVersions, DEFAULT_BODY_LIMIT,
+};
+use crate::policy::{AuthPolicy, Enforcement};
+use crate::registry::SignerSelector;
+use bytes::Bytes;
+use http::request::Parts;
+use http::uri::Authority;
+use http::{header, HeaderMap, Request, Response, StatusCode, Uri};
+use http_body::Body;
+use http_body_util::{BodyExt, Either, Full, LengthLimitError, Limited};
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::task::JoinError;
+use tower_layer::Layer;
+use tower_service::Service;
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
+
+/// The body `AuthenticationService` passes on to the inner service: the buffered body of a request
+/// that was authenticated, or the untouched body of a request to an exempt route
+pub type AuthenticatedBody<B> = Either<Full<Bytes>, B>;
+
+/// A tower layer that authenticates MAuth signed requests before passing them on to the inner
+/// service.
+///
+/// The request body is buffered, up to a limit, so that it can be verified, and the inner service
+/// receives it as a `Full<Bytes>` body within an `AuthenticatedBody`. The signature is verified on tokio's blocking thread pool
+/// so that RSA operations do not hold up the async executor. Requests that fail authentication
+/// are rejected with `401 Unauthorized`, or `413 Payload Too Large` if the body is over the limit,
+/// and an empty body. Requests that pass have an `http::AuthenticatedApp` inserted into their
+/// extensions.
+///
+/// Routes are handled according to the layer's `policy::AuthPolicy`, which by default
+/// authenticates every request and rejects failures. Requests to exempt routes are passed on
+/// without being authenticated or buffered, with their original body. Apps that are not on a
+/// route's allowlist are rejected with `403 Forbidden`. Requests to routes in shadow mode are let
+/// through without an `AuthenticatedApp` when they fail, though bodies over the limit are still
+/// rejected as they cannot be passed on.
+///
+/// ```
+/// # use mauth_core::authenticator::Authenticator;
+/// # use mauth_core::http::RequestAuthenticator;
+/// # use mauth_core::tower_server::AuthenticationLayer;
+/// # use mauth_core::verifier::RotatingVerifier;
+/// # use std::collections::HashMap;
+/// # use std::sync::Arc;
+/// # let verifiers: HashMap<String, Arc<RotatingVerifier>> = HashMap::new();
+/// # use mauth_core::policy::{AuthPolicy, Enforcement};
+/// let mut policy = AuthPolicy::new(Enforcement::Shadow);
+/// policy.route("/admin/**", Enforcement::Enforce).unwrap();
+///
+/// let layer = AuthenticationLayer::new(RequestAuthenticator::new(Authenticator::new(verifiers)))
+///     .with_body_limit(1024 * 1024)
+///     .with_policy(policy);
+/// ```
+#[derive(Debug, Clone)]
+pub struct AuthenticationLayer {
+    authenticator: Arc<RequestAuthenticator>,
+    policy: Arc<AuthPolicy>,
+    body_limit: usize,
+}
+
+impl AuthenticationLayer {
+    /// Initialize a layer that authenticates requests with `authenticator`, buffering bodies of up
+    /// to `http::DEFAULT_BODY_LIMIT` bytes.
+    pub fn new(authenticator: RequestAuthenticator) -> Self {
+        Self {
+            authenticator: Arc::new(authenticator),
+            policy: Arc::default(),
+            body_limit: DEFAULT_BODY_LIMIT,
+        }
+    }
+
+    /// Set the policy deciding how failures are handled on each route
+    pub fn with_policy(mut self, policy: AuthPolicy) -> Self {
+        self.policy = Arc::new(policy);
+        self
+    }
+
+    /// Set the largest request body, in bytes, that will be buffered for verification
+    pub fn with_body_limit(mut self, body_limit: usize) -> Self {
+        self.body_limit = body_limit;
+        self
+    }
+}
+
+impl<S> Layer<S> for AuthenticationLayer {
+    type Service = AuthenticationService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AuthenticationService {
+            inner,
+            authenticator: self.authenticator.clone(),
+            policy: self.policy.clone(),
+            body_limit: self.body_limit,
+        }
+    }
+}
+
+/// The service created by `AuthenticationLayer`
+#[derive(Debug, Clone)]
+pub struct AuthenticationService<S> {
+    inner: S,
+    authenticator: Arc<RequestAuthenticator>,
+    policy: Arc<AuthPolicy>,
+    body_limit: usize,
+}
+
+impl<S, B, ResBody> Service<Request<B>> for AuthenticationService<S>
+where
+    S: Service<Request<AuthenticatedBody<B>>, Response = Response<ResBody>>
+        + Clone
+        + Send
+        + 'static,
+    S::Future: Send,
+    B: Body<Data = Bytes> + Send + 'static,
+    B::Error: Into<BoxError>,
+    ResBody: Default,
+{
+    type Response = Response<ResBody>;
+    type Error = S::Error;
+    type Future = BoxFuture<Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        // The clone is not guaranteed to be ready, so the ready service is taken for this call
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let authenticator = self.authenticator.clone();
+        let policy = self.policy.clone();
+        let body_limit = self.body_limit;
+
+        Box::pin(async move {
+            let (parts, body) = request.into_parts();
+            if policy.enforcement(parts.method.as_str(), parts.uri.path()) == Enforcement::Exempt {
+                return inner
+                    .call(Request::from_parts(parts, Either::Right(body)))
+                    .await;
+            }
+            let body = match read_body(body, body_limit).await {
+                Ok(body) => body,
+                Err(e) => return Ok(rejection(rejection_status(&e))),
+            };
+
+            let Ok((mut parts, body, result)) =
+                authenticate_blocking(authenticator, parts, body).await
+            else {
+                return Ok(rejection(StatusCode::INTERNAL_SERVER_ERROR));
+            };
+            let app_uuid = result
+                .is_err()
+                .then(|| claimed_app_uuid(&parts.headers))
+                .flatten();
+            match policy.apply(
+                parts.method.as_str(),
+                parts.uri.path(),
+                app_uuid.as_deref(),
+                result,
+            ) {
+                Ok(Some(app)) => {
+                    parts.extensions.insert(app);
+                }
+                Ok(None) => {}
+                Err(e) => return Ok(rejection(rejection_status(&e))),
+            }
+
+            inner
+                .call(Request::from_parts(parts, Either::Left(Full::new(body))))
+                .await
+        })
+    }
+}
+
+/// A tower layer that signs the responses of the inner service with MAuth, so that clients can
+/// check that they came from this app.
+///
+/// The response body is buffered, up to a limit, and signed on tokio's blocking thread pool along
+/// with the status code, then sent on as a `Full<Bytes>` body with the `MCC-Authentication` and
+/// `MCC-Time` headers, and the V1 headers if they are enabled. The signer is chosen by a
+/// `registry::SignerSelector` from the host and path of the request, which for most services is
+/// simply their `Signer` or `RotatingSigner`.
+///
+/// Responses that cannot be signed, because no signer was selected or the body could not be read
+/// within the limit, are replaced with an empty `500 Internal Server Error` response, as clients
+/// verifying responses would reject them anyway. The reason is logged at error level.
+///
+/// # Body limit
+///
+/// Every response body is held in memory to be signed, so any response larger than the body
+/// limit, `http::DEFAULT_BODY_LIMIT` unless set with `ResponseSigningLayer::with_body_limit`, is
+/// **replaced with an empty 500 response** rather than sent unsigned. Services that return large
+/// downloads or streams should raise the limit or only apply the layer to the routes whose
+/// responses clients verify.
+///
+/// ```
+/// # use mauth_core::http::SigningVersions;
+/// # use mauth_core::keys::{KeyPair, KeySize, PrivateKeyFormat};
+/// # use mauth_core::signer::Signer;
+/// # use mauth_core::tower_server::ResponseSigningLayer;
+/// # let signer = Signer::new("101c139a-236c-11ef-b5e3-125eb8485a60", KeyPair::generate(KeySize::Rsa2048).unwrap().private_key_pem(PrivateKeyFormat::Pkcs1).unwrap().to_string()).unwrap();
+/// let layer = ResponseSigningLayer::new(signer).with_versions(SigningVersions::Both);
+/// ```
+#[derive(Clone)]
+pub struct ResponseSigningLayer {
+    selector: Arc<dyn SignerSelector>,
+    versions: SigningVersions,
+    body_limit: usize,
+}
+
+impl fmt::Debug for ResponseSigningLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResponseSigningLayer")
+            .field("versions", &self.versions)
+            .field("body_limit", &self.body_limit)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ResponseSigningLayer {
+    /// Initialize a layer that signs responses with the signer chosen by `selector` using V2,
+    /// buffering bodies of up to `http::DEFAULT_BODY_LIMIT` bytes.
+    pub fn new(selector: impl SignerSelector + 'static) -> Self {
+        Self {
+            selector: Arc::new(selector),
+            versions: SigningVersions::default(),
+            body_limit: DEFAULT_BODY_LIMIT,
+        }
+    }
+
+    /// Set the MAuth protocol versions responses are signed with
+    pub fn with_versions(mut self, versions: SigningVersions) -> Self {
+        self.versions = versions;
+        self
+    }
+
+    /// Set the largest response body, in bytes, that will be buffered for signing. Larger responses
+    /// are replaced with an empty `500 Internal Server Error` response.
+    pub fn with_body_limit(mut self, body_limit: usize) -> Self {
+        self.body_limit = body_limit;
+        self
+    }
+
+    async fn sign<ResBody>(
+        &self,
+        host: Option<String>,
+        path: String,
+        response: Response<ResBody>,
+    ) -> Result<Response<Full<Bytes>>, Error>
+    where
+        ResBody: Body,
+        ResBody::Error: Into<BoxError>,
+    {
+        let signer = self
+            .selector
+            .select_signer(host.as_deref(), &path)
+            .ok_or_else(|| Error::NoSignerSelected(host.unwrap_or(path)))?;
+        let (mut parts, body) = response.into_parts();
+        let body = read_body(body, self.body_limit).await?;
+
+        let versions = self.versions;
+        let status = parts.status.as_u16();
+        let (body, headers) = tokio::task::spawn_blocking(move || {
+            let headers = response_signature_headers(&signer, versions, status, &body);
+            (body, headers)
+        })
+        .await
+        .map_err(|e| Error::IoError(std::io::Error::other(e)))?;
+        for (name, value) in headers? {
+            parts.headers.insert(name, value);
+        }
+
+        Ok(Response::from_parts(parts, Full::new(body)))
+    }
+}
+
+impl<S> Layer<S> for ResponseSigningLayer {
+    type Service = ResponseSigningService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ResponseSigningService {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// The service created by `ResponseSigningLayer`
+#[derive(Debug, Clone)]
+pub struct ResponseSigningService<S> {
+    inner: S,
+    layer: ResponseSigningLayer,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for ResponseSigningService<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>> + Clone + Send + 'static,
+    S::Future: Send,
+    ReqBody: Send + 'static,
+    ResBody: Body + Send + 'static,
+    ResBody::Data: Send,
+    ResBody::Error: Into<BoxError>,
+{
+    type Response = Response<Full<Bytes>>;
+    type Error = S::Error;
+    type Future = BoxFuture<Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<ReqBody>) -> Self::Future {
+        // The clone is not guaranteed to be ready, so the ready service is taken for this call
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let layer = self.layer.clone();
+        let host = request_host(request.uri(), request.headers());
+        let path = request.uri().path().to_owned();
+
+        Box::pin(async move {
+            let response = inner.call(request).await?;
+            match layer.sign(host, path, response).await {
+                Ok(response) => Ok(response),
+                Err(e) => {
+                    log::error!("Unable to sign MAuth response: {e}");
+                    Ok(rejection(StatusCode::INTERNAL_SERVER_ERROR))
+                }
+            }
+        })
+    }
+}
+
+/// Read a whole body, failing with `Error::BodyTooLarge` if it is longer than `limit` bytes
+pub(crate) async fn read_body<B>(body: B, limit: usize) -> Result<Bytes, Error>
+where
+    B: Body,
+    B::Error: Into<BoxError>,
+{
+    match Limited::new(body, limit).collect().await {
+        Ok(collected) => Ok(collected.to_bytes()),
+        Err(e) if e.is::<LengthLimitError>() => Err(Error::BodyTooLarge(limit)),
+        Err(e) => Err(Error::BodyReadError(e)),
+    }
+}
+
+/// Authenticate a request on tokio's blocking thread pool, handing its parts and body back
+pub(crate) async fn authenticate_blocking(
+    authenticator: Arc<RequestAuthenticator>,
+    parts: Parts,
+    body: Bytes,
+) -> Result<(Parts, Bytes, Result<AuthenticatedApp, Error>), JoinError> {
+    tokio::task::spawn_blocking(move || {
+        let result = authenticator.authenticate_request(&parts, &body);
+        (parts, body, result)
+    })
+    .await
+}
+
+/// The host a request is for, from its URI or, for origin-form URIs, from its `Host` header
+pub(crate) fn request_host(uri: &Uri, headers: &HeaderMap) -> Option<String> {
+    uri.host().map(str::to_owned).or_else(|| {
+        let host = headers.get(header::HOST)?.to_str().ok()?;
+        Some(host.parse::<Authority>().ok()?.host().to_owned())
+    })
+}
+
+/// The app a request claims to come from, for reporting requests that failed authentication
+pub(crate) fn claimed_app_uuid(headers: &HeaderMap) -> Option<String> {
+    MAuthHeaders::parse(|name| headers.get(name)?.to_str().ok(), false)
+        .ok()
+        .map(|headers| headers.app_uuid)
+}
+
+fn rejection<ResBody: Default>(status: StatusCode) -> Response<ResBody> {
+    let mut response = Response::new(ResBody::default());
+    *response.status_mut() = status;
+    response
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::http::tests::{authenticator, now};
+    use crate::http::{MCC_AUTHENTICATION, MCC_TIME, X_MWS_AUTHENTICATION};
+    use crate::keys::tests::{signer, APP_UUID};
+    use crate::registry::SignerRegistry;
+    use std::convert::Infallible;
+    use std::sync::Mutex;
+    use tower::{service_fn, ServiceExt};
+
+    fn service(
+    ) -> impl Service<Request<Full<Bytes>>, Response = Response<Full<Bytes>>, Error = Infallible> + Clone
+    {
+        service_with_policy(AuthPolicy::default())
+    }
+
+    /// A service echoing the request body, with the authenticated app UUID in a header
+    fn service_with_policy(
+        policy: AuthPolicy,
+    ) -> impl Service<Request<Full<Bytes>>, Response = Response<Full<Bytes>>, Error = Infallible> + Clone
+    {
+        AuthenticationLayer::new(RequestAuthenticator::new(authenticator()))
+            .with_body_limit(16)
+            .with_policy(policy)
+            .layer(service_fn(
+                |request: Request<AuthenticatedBody<Full<Bytes>>>| async move {
+                    let app = request.extensions().get::<AuthenticatedApp>().cloned();
+                    let body = request.into_body().collect().await.unwrap().to_bytes();
+                    let app_uuid = app.map_or("none".to_owned(), |app| app.app_uuid);
+
+                    Ok::<_, Infallible>(
+                        Response::builder()
+                            .header("app-uuid", app_uuid)
+                            .body(Full::new(body))
+                            .unwrap(),
+                    )
+                },
+            ))
+    }
+
+    pub(crate) fn signed_request(body: &'static [u8]) -> Request<Full<Bytes>> {
+        let time = now();
+        let signature = signer()
+            .sign_string(2, "PUT", "/items/1", "", body, &time)
+            .unwrap();
+        Request::put("/items/1")
+            .header(MCC_AUTHENTICATION, format!("MWSV2 {APP_UUID}:{signature};"))
+            .header(MCC_TIME, time)
+            .body(Full::new(Bytes::from_static(body)))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn signed_requests_reach_the_inner_service() {
+        let response = service().oneshot(signed_request(b"{}")).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["app-uuid"], APP_UUID);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "{}");
+    }
+
+    #[tokio::test]
+    async fn unauthenticated_requests_are_rejected() {
+        let mut tampered = signed_request(b"{}");
+        *tampered.body_mut() = Full::new(Bytes::from_static(b"[]"));
+        let unsigned = Request::get("/").body(Full::default()).unwrap();
+
+        for request in [tampered, unsigned] {
+            let response = service().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        }
+    }
+
+    #[tokio::test]
+    async fn shadowed_routes_let_failures_through() {
+        let failures = Arc::new(Mutex::new(Vec::new()));
+        let mut policy = AuthPolicy::new(Enforcement::Enforce).with_failure_handler({
+            let failures = failures.clone();
+            move |failure| failures.lock().unwrap().push(failure.error.to_string())
+        });
+        policy.route("/items/**", Enforcement::Shadow).unwrap();
+        let mut tampered = signed_request(b"{}");
+        *tampered.body_mut() = Full::new(Bytes::from_static(b"[]"));
+
+        let response = service_with_policy(policy.clone())
+            .oneshot(tampered)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["app-uuid"], "none");
+
+        let response = service_with_policy(policy)
+            .oneshot(Request::get("/").body(Full::default()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(failures.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn policy_exempts_routes_and_restricts_apps() {
+        let mut policy = AuthPolicy::default();
+        policy.route("GET /health", Enforcement::Exempt).unwrap();
+        policy.allow_apps("/items/**", ["other-app"]).unwrap();
+
+        let response = service_with_policy(policy.clone())
+            .oneshot(Request::get("/health").body(Full::default()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = service_with_policy(policy)
+            .oneshot(signed_request(b"{}"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn exempt_routes_pass_bodies_on_unbuffered() {
+        let mut policy = AuthPolicy::default();
+        policy.route("POST /uploads", Enforcement::Exempt).unwrap();
+        let request = Request::post("/uploads")
+            .body(Full::new(Bytes::from_static(b"0123456789abcdefg")))
+            .unwrap();
+
+        let response = service_with_policy(policy).oneshot(request).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "0123456789abcdefg");
+    }
+
+    #[tokio::test]
+    async fn oversized_bodies_are_rejected() {
+        let response = service()
+            .oneshot(signed_request(b"0123456789abcdefg"))
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    fn signed_responses(
+        layer: ResponseSigningLayer,
+    ) -> impl Service<Request<Full<Bytes>>, Response = Response<Full<Bytes>>, Error = Infallible>
+    {
+        layer.layer(service_fn(|_: Request<Full<Bytes>>| async {
+            Ok::<_, Infallible>(
+                Response::builder()
+                    .status(StatusCode::CREATED)
+                    .body(Full::new(Bytes::from_static(b"{}")))
+                    .unwrap(),
+            )
+        }))
+    }
+
+    #[tokio::test]
+    async fn responses_are_signed() {
+        let layer = ResponseSigningLayer::new(signer()).with_versions(SigningVersions::Both);
+        let request = Request::get("/items").body(Full::default()).unwrap();
+
+        let response = signed_responses(layer).oneshot(request).await.unwrap();
+
+        let (parts, body) = response.into_parts();
+        let body = body.collect().await.unwrap().to_bytes();
+        assert_eq!(parts.status, StatusCode::CREATED);
+        assert!(parts.headers.contains_key(X_MWS_AUTHENTICATION));
+        let app = RequestAuthenticator::new(authenticator())
+            .authenticate_response(parts.status.as_u16(), &body, |name| {
+                parts.headers.get(name)?.to_str().ok()
+            })
+            .unwrap();
+        assert_eq!((app.app_uuid.as_str(), app.version), (APP_UUID, 2));
+    }
+
+    #[tokio::test]
+    async fn responses_that_cannot_be_signed_are_replaced() {
+        let mut registry = SignerRegistry::new();
+        registry.insert(signer());
+        registry.route_host("api.example.com", APP_UUID).unwrap();
+        let request = |host| {
+            Request::get("/items")
+                .header(header::HOST, host)
+                .body(Full::default())
+                .unwrap()
+        };
+
+        let response = signed_responses(ResponseSigningLayer::new(registry.clone()))
+            .oneshot(request("api.example.com:8443"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let response = signed_responses(ResponseSigningLayer::new(registry))
+            .oneshot(request("other.example.com"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(!response.headers().contains_key(MCC_AUTHENTICATION));
+
+        let response = signed_responses(ResponseSigningLayer::new(signer()).with_body_limit(1))
+            .oneshot(request("api.example.com"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[tokio::test]
+    async fn response_signers_are_selected_before_bodies_are_read() {
+        let mut registry = SignerRegistry::new();
+        registry.insert(signer());
+        registry.route_host("api.example.com", APP_UUID).unwrap();
+        let layer = ResponseSigningLayer::new(registry).with_body_limit(1);
+        let response = Response::new(Full::new(Bytes::from_static(b"{}")));
+
+        let error = layer
+            .sign(
+                Some("other.example.com".to_owned()),
+                "/".to_owned(),
+                response,
+            )
+            .await
+            .unwrap_err();
+
+        assert!(matches!(error, Error::NoSignerSelected(host) if host == "other.example.com"));
+    }
+}
@@ -0,0 +1,348 @@
+use crate::authenticator::KeyProvider;
+use crate::error::{Error, TrustStoreEntryError};
+use crate::keys::KeyPolicy;
+use crate::verifier::{RotatingVerifier, VerificationKey, Verifier};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The file formats a trust store can be read from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrustStoreFormat {
+    /// JSON, as an object with an `apps` array of entries
+    Json,
+    /// TOML, as an array of `[[apps]]` tables
+    Toml,
+}
+
+impl TrustStoreFormat {
+    /// The format matching a file's extension, `.json` or `.toml`
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "json" => Some(TrustStoreFormat::Json),
+            "toml" => Some(TrustStoreFormat::Toml),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TrustStoreFile {
+    #[serde(default)]
+    apps: Vec<TrustStoreEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TrustStoreEntry {
+    app_uuid: String,
+    name: Option<String>,
+    public_key: String,
+    not_before: Option<u64>,
+    expires_at: Option<u64>,
+}
+
+/// An app known to a `TrustStore`
+#[derive(Debug, Clone)]
+pub struct TrustedApp {
+    name: Option<String>,
+    verifier: Arc<RotatingVerifier>,
+}
+
+impl TrustedApp {
+    /// The human readable name of the app, if the trust store gave one
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// The verifier holding the app's keys
+    pub fn verifier(&self) -> &Arc<RotatingVerifier> {
+        &self.verifier
+    }
+}
+
+/// A static mapping of app UUIDs to their public keys, for deployments that cannot reach a MAuth
+/// service. It is a `KeyProvider`, so it can be passed to `Authenticator::new` to verify requests
+/// from any of its apps.
+///
+/// Each entry in the file has an `app_uuid`, a `public_key` in any format accepted by
+/// `Verifier::new`, and optionally a `name` and the `not_before` and `expires_at` times of the key
+/// as Unix timestamps. An app can have several entries, which are tried in file order, to allow
+/// for key rotation. For example, in TOML:
+///
+/// ```toml
+/// [[apps]]
+/// app_uuid = "101c139a-236c-11ef-b5e3-125eb8485a60"
+/// name = "Example app"
+/// expires_at = 1767225600
+/// public_key = """
+/// -----BEGIN PUBLIC KEY-----
+/// ...
+/// -----END PUBLIC KEY-----
+/// """
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct TrustStore {
+    apps: HashMap<String, TrustedApp>,
+}
+
+impl TrustStore {
+    /// Read a trust store file, choosing the format from its extension. Keys must meet the default
+    /// `KeyPolicy`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::load_with_policy(path, &KeyPolicy::default())
+    }
+
+    /// Read a trust store file in the same way as `TrustStore::load`, checking keys against the
+    /// provided `KeyPolicy` instead of the default one.
+    pub fn load_with_policy(path: impl AsRef<Path>, policy: &KeyPolicy) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let format = TrustStoreFormat::from_path(path).ok_or_else(|| {
+            Error::TrustStoreParseError(format!(
+                "unknown trust store format for {}",
+                path.display()
+            ))
+        })?;
+
+        Self::parse_with_policy(&std::fs::read_to_string(path)?, format, policy)
+    }
+
+    /// Parse the contents of a trust store. Keys must meet the default `KeyPolicy`. Every entry is
+    /// validated, and if any are invalid `Error::InvalidTrustStoreEntries` is returned listing them
+    /// all.
+    ///
+    /// ```
+    /// # use mauth_core::authenticator::Authenticator;
+    /// # use mauth_core::keys::{KeyPair, KeySize, PublicKeyFormat};
+    /// # use mauth_core::trust_store::{TrustStore, TrustStoreFormat};
+    /// # let public_key = KeyPair::generate(KeySize::Rsa2048).unwrap().public_key_pem(PublicKeyFormat::Spki).unwrap();
+    /// let contents = serde_json::json!({
+    ///     "apps": [{
+    ///         "app_uuid": "101c139a-236c-11ef-b5e3-125eb8485a60",
+    ///         "name": "Example app",
+    ///         "public_key": public_key,
+    ///     }]
+    /// });
+    /// let trust_store = TrustStore::parse(&contents.to_string(), TrustStoreFormat::Json).unwrap();
+    /// let app = trust_store.app("101c139a-236c-11ef-b5e3-125eb8485a60").unwrap();
+    /// assert_eq!(app.name(), Some("Example app"));
+    ///
+    /// let authenticator = Authenticator::new(trust_store);
+    /// ```
+    pub fn parse(contents: &str, format: TrustStoreFormat) -> Result<Self, Error> {
+        Self::parse_with_policy(contents, format, &KeyPolicy::default())
+    }
+
+    /// Parse the contents of a trust store in the same way as `TrustStore::parse`, checking keys
+    /// against the provided `KeyPolicy` instead of the default one.
+    pub fn parse_with_policy(
+        contents: &str,
+        format: TrustStoreFormat,
+        policy: &KeyPolicy,
+    ) -> Result<Self, Error> {
+        let file: TrustStoreFile = match format {
+            TrustStoreFormat::Json => serde_json::from_str(contents)
+                .map_err(|e| Error::TrustStoreParseError(e.to_string()))?,
+            TrustStoreFormat::Toml => {
+                toml::from_str(contents).map_err(|e| Error::TrustStoreParseError(e.to_string()))?
+            }
+        };
+
+        let mut apps: HashMap<String, (Option<String>, RotatingVerifier)> = HashMap::new();
+        let mut errors = Vec::new();
+        for (index, entry) in file.apps.into_iter().enumerate() {
+            let app_uuid = entry.app_uuid.clone();
+            let (name, verifier) = apps
+                .entry(entry.app_uuid.clone())
+                .or_insert_with(|| (None, RotatingVerifier::new(&entry.app_uuid)));
+            if name.is_none() {
+                name.clone_from(&entry.name);
+            }
+
+            if let Err(source) =
+                Self::entry_key(entry, policy).and_then(|key| verifier.add_key(key))
+            {
+                errors.push(TrustStoreEntryError {
+                    index,
+                    app_uuid,
+                    source: Box::new(source),
+                });
+            }
+        }
+        if !errors.is_empty() {
+            return Err(Error::InvalidTrustStoreEntries(errors));
+        }
+
+        Ok(Self {
+            apps: apps
+                .into_iter()
+                .map(|(app_uuid, (name, verifier))| {
+                    let verifier = Arc::new(verifier);
+                    (app_uuid, TrustedApp { name, verifier })
+                })
+                .collect(),
+        })
+    }
+
+    /// The app with the given UUID, if the trust store has it
+    pub fn app(&self, app_uuid: &str) -> Option<&TrustedApp> {
+        self.apps.get(app_uuid)
+    }
+
+    /// The UUIDs of all of the apps in the trust store
+    pub fn app_uuids(&self) -> impl Iterator<Item = &str> {
+        self.apps.keys().map(String::as_str)
+    }
+
+    /// The number of apps in the trust store
+    pub fn len(&self) -> usize {
+        self.apps.len()
+    }
+
+    /// Whether the trust store has no apps
+    pub fn is_empty(&self) -> bool {
+        self.apps.is_empty()
+    }
+
+    fn entry_key(entry: TrustStoreEntry, policy: &KeyPolicy) -> Result<VerificationKey, Error> {
+        let invalid = |reason: &str| Error::InvalidTrustStoreEntry {
+            app_uuid: entry.app_uuid.clone(),
+            reason: reason.to_owned(),
+        };
+        if entry.app_uuid.is_empty() {
+            return Err(invalid("app_uuid is empty"));
+        }
+        if let (Some(not_before), Some(expires_at)) = (entry.not_before, entry.expires_at) {
+            if expires_at < not_before {
+                return Err(invalid("expires_at is before not_before"));
+            }
+        }
+
+        let verifier = Verifier::new_with_policy(&entry.app_uuid, entry.public_key, policy)?;
+        let mut key = VerificationKey::new(verifier);
+        if let Some(not_before) = entry.not_before {
+            key = key.with_not_before(
+                unix_time(not_before).ok_or_else(|| invalid("not_before is out of range"))?,
+            );
+        }
+        if let Some(expires_at) = entry.expires_at {
+            key = key.with_not_after(
+                unix_time(expires_at).ok_or_else(|| invalid("expires_at is out of range"))?,
+            );
+        }
+
+        Ok(key)
+    }
+}
+
+impl KeyProvider for TrustStore {
+    fn verifier(&self, app_uuid: &str) -> Result<Option<Arc<RotatingVerifier>>, Error> {
+        Ok(self.apps.get(app_uuid).map(|app| app.verifier.clone()))
+    }
+}
+
+/// The time `seconds` after the Unix epoch, or `None` if it cannot be represented
+fn unix_time(seconds: u64) -> Option<SystemTime> {
+    UNIX_EPOCH.checked_add(Duration::from_secs(seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::tests::{key_pair, signer, APP_UUID};
+    use crate::keys::PublicKeyFormat;
+
+    fn public_key() -> String {
+        key_pair().public_key_pem(PublicKeyFormat::Spki).unwrap()
+    }
+
+    #[test]
+    fn parse_reads_toml() {
+        let contents = format!(
+            "[[apps]]\napp_uuid = \"{APP_UUID}\"\nname = \"Example\"\npublic_key = \"\"\"\n{}\"\"\"\nexpires_at = 4102444800\n",
+            public_key()
+        );
+        let trust_store = TrustStore::parse(&contents, TrustStoreFormat::Toml).unwrap();
+
+        let app = trust_store.app(APP_UUID).unwrap();
+        assert_eq!(app.name(), Some("Example"));
+        assert_eq!(
+            app.verifier().keys()[0].not_after(),
+            unix_time(4_102_444_800)
+        );
+        assert_eq!(
+            app.verifier().keys()[0].verifier().fingerprint(),
+            signer().fingerprint()
+        );
+    }
+
+    #[test]
+    fn parse_groups_entries_by_app() {
+        let contents = serde_json::json!({
+            "apps": [
+                { "app_uuid": APP_UUID, "public_key": public_key(), "expires_at": 1 },
+                { "app_uuid": APP_UUID, "public_key": public_key() },
+                { "app_uuid": "other-app", "public_key": public_key() },
+            ]
+        });
+        let trust_store = TrustStore::parse(&contents.to_string(), TrustStoreFormat::Json).unwrap();
+
+        assert_eq!(trust_store.len(), 2);
+        assert_eq!(
+            trust_store.app(APP_UUID).unwrap().verifier().keys().len(),
+            2
+        );
+    }
+
+    #[test]
+    fn parse_reports_every_invalid_entry() {
+        let contents = serde_json::json!({
+            "apps": [
+                { "app_uuid": APP_UUID, "public_key": public_key() },
+                { "app_uuid": "bad-key", "public_key": "not a key" },
+                { "app_uuid": "bad-times", "public_key": public_key(), "not_before": 2, "expires_at": 1 },
+                { "app_uuid": "", "public_key": public_key() },
+                { "app_uuid": "far-future", "public_key": public_key(), "not_before": u64::MAX },
+            ]
+        });
+        let result = TrustStore::parse(&contents.to_string(), TrustStoreFormat::Json);
+
+        match result {
+            Err(Error::InvalidTrustStoreEntries(errors)) => {
+                let entries: Vec<_> = errors
+                    .iter()
+                    .map(|e| (e.index, e.app_uuid.as_str()))
+                    .collect();
+                assert_eq!(
+                    entries,
+                    [(1, "bad-key"), (2, "bad-times"), (3, ""), (4, "far-future")]
+                );
+                assert!(matches!(*errors[0].source, Error::PublicKeyDecodeError(_)));
+                assert!(matches!(
+                    &*errors[1].source,
+                    Error::InvalidTrustStoreEntry { app_uuid, .. } if app_uuid == "bad-times"
+                ));
+                assert!(matches!(
+                    *errors[2].source,
+                    Error::InvalidTrustStoreEntry { .. }
+                ));
+                assert!(matches!(
+                    &*errors[3].source,
+                    Error::InvalidTrustStoreEntry { reason, .. } if reason.contains("not_before")
+                ));
+            }
+            other => panic!("unexpected result: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn parse_rejects_malformed_files() {
+        assert!(matches!(
+            TrustStore::parse("{\"apps\": [{\"app_uuid\": 1}]}", TrustStoreFormat::Json),
+            Err(Error::TrustStoreParseError(_))
+        ));
+    }
+}
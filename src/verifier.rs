@@ -1,17 +1,34 @@
-use crate::{error::Error, signable::Signable};
+use crate::error::Error;
+use crate::keys::{KeyFingerprint, KeyPolicy};
+use crate::revocation::RevocationList;
+use crate::signable::{Signable, SignableMessage, SignableResponse, SigningString};
 use base64::{engine::general_purpose, Engine as _};
 use rsa::pkcs1::DecodeRsaPublicKey;
 use rsa::pkcs1v15::Signature;
-use rsa::pkcs8::DecodePublicKey;
+use rsa::pkcs8::{DecodePublicKey, EncodePublicKey};
 use rsa::RsaPublicKey;
 use sha2::Sha512;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 /// Used to verify incoming requests. Struct can be initialized once and used to verify many requests.
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct Verifier {
     app_uuid: String,
+    fingerprint: KeyFingerprint,
     public_key: RsaPublicKey,
     verifying_key: rsa::pkcs1v15::VerifyingKey<Sha512>,
+    revocations: Option<Arc<RevocationList>>,
+}
+
+impl fmt::Debug for Verifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Verifier")
+            .field("app_uuid", &self.app_uuid)
+            .field("fingerprint", &self.fingerprint)
+            .finish_non_exhaustive()
+    }
 }
 
 impl Verifier {
@@ -22,7 +39,8 @@ impl Verifier {
     /// `openssl rsa -RSAPublicKey_out`. An error will be returned if the input data cannot be parsed
     /// as either public key format. The `app_uuid` is expected to be a valid UUID, however this is
     /// not checked. If you pass something other than a valid UUID, no error will be returned, but
-    /// none of the signatures will be able to be validated.
+    /// none of the signatures will be able to be validated. The key must meet the default
+    /// `KeyPolicy`, use `Verifier::new_with_policy` to accept weaker legacy keys.
     ///
     /// ```
     /// # use mauth_core::verifier::Verifier;
@@ -31,16 +49,83 @@ impl Verifier {
     /// assert!(verifier.is_ok());
     /// ```
     pub fn new(app_uuid: impl Into<String>, public_key_data: String) -> Result<Self, Error> {
+        Self::new_with_policy(app_uuid, public_key_data, &KeyPolicy::default())
+    }
+
+    /// Initialize a new verifier in the same way as `Verifier::new`, but checking the public key
+    /// against the provided `KeyPolicy` instead of the default one.
+    ///
+    /// ```
+    /// # use mauth_core::keys::KeyPolicy;
+    /// # use mauth_core::verifier::Verifier;
+    /// # let public_key = std::fs::read_to_string("tests/mauth-protocol-test-suite/signing-params/rsa-key-pub").unwrap();
+    /// let verifier = Verifier::new_with_policy("101c139a-236c-11ef-b5e3-125eb8485a60", public_key, &KeyPolicy::legacy());
+    /// assert!(verifier.is_ok());
+    /// ```
+    pub fn new_with_policy(
+        app_uuid: impl Into<String>,
+        public_key_data: String,
+        policy: &KeyPolicy,
+    ) -> Result<Self, Error> {
         let public_key = RsaPublicKey::from_public_key_pem(&public_key_data).or_else(|_| {
             RsaPublicKey::from_pkcs1_pem(&public_key_data).map_err(spki::Error::from)
         })?;
+        policy.check(&public_key)?;
+        let fingerprint = KeyFingerprint::from_public_key(&public_key)?;
+
+        Ok(Self::from_public_key(app_uuid, public_key, fingerprint))
+    }
+
+    pub(crate) fn from_public_key(
+        app_uuid: impl Into<String>,
+        public_key: RsaPublicKey,
+        fingerprint: KeyFingerprint,
+    ) -> Self {
         let verifying_key = rsa::pkcs1v15::VerifyingKey::<Sha512>::new(public_key.to_owned());
 
-        Ok(Self {
+        Self {
             app_uuid: app_uuid.into(),
+            fingerprint,
             public_key,
             verifying_key,
-        })
+            revocations: None,
+        }
+    }
+
+    /// Consult a revocation list after each signature is verified, returning `Error::KeyRevoked`
+    /// if the app or key has been revoked. The list can be updated after it has been attached.
+    pub fn with_revocation_list(mut self, revocations: Arc<RevocationList>) -> Self {
+        self.revocations = Some(revocations);
+        self
+    }
+
+    /// Initialize a verifier from the SubjectPublicKeyInfo DER encoding of a public key, checking
+    /// it against the key policy.
+    pub(crate) fn from_public_key_der(
+        app_uuid: impl Into<String>,
+        public_key_der: &[u8],
+        policy: &KeyPolicy,
+    ) -> Result<Self, Error> {
+        let public_key = RsaPublicKey::from_public_key_der(public_key_der)?;
+        policy.check(&public_key)?;
+        let fingerprint = KeyFingerprint::from_public_key(&public_key)?;
+
+        Ok(Self::from_public_key(app_uuid, public_key, fingerprint))
+    }
+
+    /// The SubjectPublicKeyInfo DER encoding of the public key
+    pub(crate) fn public_key_der(&self) -> Result<Vec<u8>, Error> {
+        Ok(self
+            .public_key
+            .to_public_key_der()
+            .map_err(|e| Error::KeyEncodeError(e.into()))?
+            .into_vec())
+    }
+
+    /// The fingerprint of the public key used to verify signatures. It is also included in the
+    /// `Error::SignatureVerifyError` returned when a signature does not match.
+    pub fn fingerprint(&self) -> KeyFingerprint {
+        self.fingerprint
     }
 
     /// This function will verify that a provided signature is valid given the uuid and public key the
@@ -56,7 +141,7 @@ impl Verifier {
     /// # let verifier = Verifier::new("101c139a-236c-11ef-b5e3-125eb8485a60", public_key).unwrap();
     /// let result = verifier.verify_signature(2, "GET", "/item", "page=2", b"", "2024-01-28T19:11:35.000", "");
     /// // Passing in an empty signature, so it will result in a verification error
-    /// assert!(matches!(result, Err(Error::SignatureVerifyError(_))));
+    /// assert!(matches!(result, Err(Error::SignatureVerifyError { .. })));
     /// ```
     #[allow(clippy::too_many_arguments)]
     pub fn verify_signature(
@@ -71,40 +156,326 @@ impl Verifier {
     ) -> Result<(), Error> {
         let signable = Signable::new(verb, path, query, body, timestamp, &self.app_uuid);
 
+        self.verify_signable(version, &signable, &signature.into())
+    }
+
+    /// Verify the signature of a response in the same way as `Verifier::verify_signature`, given
+    /// its status code and body.
+    ///
+    /// ```
+    /// # use mauth_core::keys::{KeyPair, KeySize, PrivateKeyFormat};
+    /// # use mauth_core::signer::Signer;
+    /// # let signer = Signer::new("101c139a-236c-11ef-b5e3-125eb8485a60", KeyPair::generate(KeySize::Rsa2048).unwrap().private_key_pem(PrivateKeyFormat::Pkcs1).unwrap().to_string()).unwrap();
+    /// # let verifier = signer.verifier();
+    /// let signature = signer.sign_response(1, 404, b"", "1669858655").unwrap();
+    /// assert!(verifier.verify_response_signature(1, 404, b"", "1669858655", &signature).is_ok());
+    /// assert!(verifier.verify_response_signature(1, 200, b"", "1669858655", &signature).is_err());
+    /// ```
+    pub fn verify_response_signature(
+        &self,
+        version: u8,
+        status: u16,
+        body: &[u8],
+        timestamp: impl Into<String>,
+        signature: impl Into<String>,
+    ) -> Result<(), Error> {
+        let signable = SignableResponse::new(status, body, timestamp, &self.app_uuid);
+
+        self.verify_signable(version, &signable, &signature.into())
+    }
+
+    /// Verify the signature of a message published to `topic`, as created by
+    /// `Signer::sign_message`.
+    pub fn verify_message_signature(
+        &self,
+        topic: impl Into<String>,
+        payload: &[u8],
+        timestamp: impl Into<String>,
+        signature: impl Into<String>,
+    ) -> Result<(), Error> {
+        let signable = SignableMessage::new(topic, payload, timestamp, &self.app_uuid);
+
+        self.verify_signable(2, &signable, &signature.into())
+    }
+
+    /// The app UUID that signatures are expected to come from
+    pub fn app_uuid(&self) -> &str {
+        &self.app_uuid
+    }
+
+    fn verify_signable(
+        &self,
+        version: u8,
+        signable: &impl SigningString,
+        signature: &str,
+    ) -> Result<(), Error> {
         match version {
-            1 => self.verify_signature_v1(&signable, signature.into()),
-            2 => self.verify_signature_v2(&signable, signature.into()),
-            v => Err(Error::UnsupportedVersion(v)),
+            1 => self.verify_signature_v1(signable, signature)?,
+            2 => self.verify_signature_v2(signable, signature)?,
+            v => return Err(Error::UnsupportedVersion(v)),
         }
-    }
 
-    fn verify_signature_v1(&self, signable: &Signable, signature: String) -> Result<(), Error> {
-        self.public_key.verify(
-            rsa::Pkcs1v15Sign::new_unprefixed(),
-            &signable.signing_string_v1()?,
-            &general_purpose::STANDARD.decode(signature)?,
-        )?;
+        match &self.revocations {
+            Some(revocations) => {
+                revocations.check(&self.app_uuid, &self.fingerprint, SystemTime::now())
+            }
+            None => Ok(()),
+        }
+    }
 
-        Ok(())
+    fn verify_signature_v1(
+        &self,
+        signable: &impl SigningString,
+        signature: &str,
+    ) -> Result<(), Error> {
+        self.public_key
+            .verify(
+                rsa::Pkcs1v15Sign::new_unprefixed(),
+                &signable.signing_string_v1()?,
+                &general_purpose::STANDARD.decode(signature)?,
+            )
+            .map_err(|e| self.verify_error(rsa::signature::Error::from_source(e)))
     }
 
-    fn verify_signature_v2(&self, signable: &Signable, signature: String) -> Result<(), Error> {
+    fn verify_signature_v2(
+        &self,
+        signable: &impl SigningString,
+        signature: &str,
+    ) -> Result<(), Error> {
         use rsa::signature::Verifier;
 
         let signature =
-            Signature::try_from(general_purpose::STANDARD.decode(signature)?.as_slice())?;
+            Signature::try_from(general_purpose::STANDARD.decode(signature)?.as_slice())
+                .map_err(|e| self.verify_error(e))?;
         self.verifying_key
-            .verify(&signable.signing_string_v2()?, &signature)?;
+            .verify(&signable.signing_string_v2()?, &signature)
+            .map_err(|e| self.verify_error(e))
+    }
+
+    fn verify_error(&self, source: rsa::signature::Error) -> Error {
+        Error::SignatureVerifyError {
+            fingerprint: self.fingerprint,
+            source,
+        }
+    }
+}
+
+/// A public key accepted by a `RotatingVerifier`, along with the optional period it is valid for.
+#[derive(Debug, Clone)]
+pub struct VerificationKey {
+    verifier: Verifier,
+    not_before: Option<SystemTime>,
+    not_after: Option<SystemTime>,
+}
+
+impl VerificationKey {
+    /// Wrap a verifier as a key that is valid at any time
+    pub fn new(verifier: Verifier) -> Self {
+        Self {
+            verifier,
+            not_before: None,
+            not_after: None,
+        }
+    }
+
+    /// Only accept signatures with this key from the given time onwards
+    pub fn with_not_before(mut self, not_before: SystemTime) -> Self {
+        self.not_before = Some(not_before);
+        self
+    }
+
+    /// Stop accepting signatures with this key after the given time
+    pub fn with_not_after(mut self, not_after: SystemTime) -> Self {
+        self.not_after = Some(not_after);
+        self
+    }
+
+    /// The verifier for this key
+    pub fn verifier(&self) -> &Verifier {
+        &self.verifier
+    }
+
+    /// The time from which the key is valid, if it has one
+    pub fn not_before(&self) -> Option<SystemTime> {
+        self.not_before
+    }
+
+    /// The time after which the key is no longer valid, if it has one
+    pub fn not_after(&self) -> Option<SystemTime> {
+        self.not_after
+    }
+
+    /// Whether the key is within its validity period at the given time
+    pub fn is_valid_at(&self, time: SystemTime) -> bool {
+        self.not_before.is_none_or(|not_before| time >= not_before)
+            && self.not_after.is_none_or(|not_after| time <= not_after)
+    }
+}
+
+/// Used to verify incoming requests from an app that is rotating its key. It holds an ordered set of
+/// keys for the same app UUID, and tries each key that is currently within its validity period in
+/// turn, so the old and new keys are both accepted during the rotation grace period.
+#[derive(Debug, Clone)]
+pub struct RotatingVerifier {
+    app_uuid: String,
+    keys: Vec<VerificationKey>,
+}
+
+impl RotatingVerifier {
+    /// Initialize a new verifier for the source app UUID with no keys
+    pub fn new(app_uuid: impl Into<String>) -> Self {
+        Self {
+            app_uuid: app_uuid.into(),
+            keys: Vec::new(),
+        }
+    }
+
+    /// Add a key to the end of the set. Keys are tried in the order they were added. An error is
+    /// returned if the key's verifier was created for a different app UUID.
+    pub fn add_key(&mut self, key: VerificationKey) -> Result<(), Error> {
+        if key.verifier.app_uuid != self.app_uuid {
+            return Err(Error::AppUuidMismatch {
+                expected: self.app_uuid.clone(),
+                actual: key.verifier.app_uuid,
+            });
+        }
+        self.keys.push(key);
 
         Ok(())
     }
+
+    /// The app UUID that signatures are expected to come from
+    pub fn app_uuid(&self) -> &str {
+        &self.app_uuid
+    }
+
+    /// The keys in the set, in the order they are tried
+    pub fn keys(&self) -> &[VerificationKey] {
+        &self.keys
+    }
+
+    /// Verify a signature in the same way as `Verifier::verify_signature`, trying each key that is
+    /// valid at the current time. On success, the fingerprint of the key that matched is returned.
+    ///
+    /// ```
+    /// # use mauth_core::keys::{KeyPair, KeySize, PrivateKeyFormat};
+    /// # use mauth_core::signer::Signer;
+    /// # use mauth_core::verifier::{RotatingVerifier, VerificationKey};
+    /// # use std::time::{Duration, SystemTime};
+    /// # let new_key = KeyPair::generate(KeySize::Rsa2048).unwrap();
+    /// # let signer = Signer::new("101c139a-236c-11ef-b5e3-125eb8485a60", new_key.private_key_pem(PrivateKeyFormat::Pkcs1).unwrap().to_string()).unwrap();
+    /// # let old_verifier = Signer::new("101c139a-236c-11ef-b5e3-125eb8485a60", KeyPair::generate(KeySize::Rsa2048).unwrap().private_key_pem(PrivateKeyFormat::Pkcs1).unwrap().to_string()).unwrap().verifier();
+    /// let grace_period_end = SystemTime::now() + Duration::from_secs(3600);
+    /// let mut verifier = RotatingVerifier::new("101c139a-236c-11ef-b5e3-125eb8485a60");
+    /// verifier.add_key(VerificationKey::new(old_verifier).with_not_after(grace_period_end)).unwrap();
+    /// verifier.add_key(VerificationKey::new(signer.verifier())).unwrap();
+    ///
+    /// let signature = signer.sign_string(2, "GET", "/item", "page=2", b"", "1669858655").unwrap();
+    /// let result = verifier.verify_signature(2, "GET", "/item", "page=2", b"", "1669858655", signature);
+    /// assert_eq!(result.unwrap(), signer.fingerprint());
+    /// ```
+    #[allow(clippy::too_many_arguments)]
+    pub fn verify_signature(
+        &self,
+        version: u8,
+        verb: impl Into<String>,
+        path: impl Into<String>,
+        query: impl Into<String>,
+        body: &[u8],
+        timestamp: impl Into<String>,
+        signature: impl Into<String>,
+    ) -> Result<KeyFingerprint, Error> {
+        let signable = Signable::new(verb, path, query, body, timestamp, &self.app_uuid);
+
+        self.verify_signable(version, &signable, &signature.into())
+    }
+
+    /// Verify the signature of a response in the same way as `Verifier::verify_response_signature`,
+    /// trying each key that is valid at the current time. On success, the fingerprint of the key
+    /// that matched is returned.
+    pub fn verify_response_signature(
+        &self,
+        version: u8,
+        status: u16,
+        body: &[u8],
+        timestamp: impl Into<String>,
+        signature: impl Into<String>,
+    ) -> Result<KeyFingerprint, Error> {
+        let signable = SignableResponse::new(status, body, timestamp, &self.app_uuid);
+
+        self.verify_signable(version, &signable, &signature.into())
+    }
+
+    /// Verify the signature of a message in the same way as `Verifier::verify_message_signature`.
+    /// Messages may be consumed long after they were published, so each key that was valid at the
+    /// Unix time in `timestamp` is tried rather than those valid at the current time, while
+    /// revocations still apply from the current time. On success, the fingerprint of the key that
+    /// matched is returned.
+    pub fn verify_message_signature(
+        &self,
+        topic: impl Into<String>,
+        payload: &[u8],
+        timestamp: impl Into<String>,
+        signature: impl Into<String>,
+    ) -> Result<KeyFingerprint, Error> {
+        let timestamp = timestamp.into();
+        let signed_at = timestamp
+            .parse()
+            .ok()
+            .and_then(|time| UNIX_EPOCH.checked_add(Duration::from_secs(time)))
+            .ok_or_else(|| {
+                Error::InvalidAuthenticationHeader(format!(
+                    "message time {timestamp:?} is not a valid Unix time"
+                ))
+            })?;
+        let signable = SignableMessage::new(topic, payload, timestamp, &self.app_uuid);
+
+        self.verify_signable_at(2, &signable, &signature.into(), signed_at)
+    }
+
+    pub(crate) fn verify_signable(
+        &self,
+        version: u8,
+        signable: &impl SigningString,
+        signature: &str,
+    ) -> Result<KeyFingerprint, Error> {
+        self.verify_signable_at(version, signable, signature, SystemTime::now())
+    }
+
+    /// Verify a signature with each key that was valid at `time`
+    pub(crate) fn verify_signable_at(
+        &self,
+        version: u8,
+        signable: &impl SigningString,
+        signature: &str,
+        time: SystemTime,
+    ) -> Result<KeyFingerprint, Error> {
+        let mut tried = Vec::new();
+        for key in self.keys.iter().filter(|key| key.is_valid_at(time)) {
+            match key.verifier.verify_signable(version, signable, signature) {
+                Ok(()) => return Ok(key.verifier.fingerprint),
+                Err(Error::SignatureVerifyError { fingerprint, .. }) => tried.push(fingerprint),
+                Err(e) => return Err(e),
+            }
+        }
+
+        match tried.is_empty() {
+            true => Err(Error::NoValidKey(self.app_uuid.clone())),
+            false => Err(Error::NoMatchingKey {
+                app_uuid: self.app_uuid.clone(),
+                fingerprints: tried,
+            }),
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::keys::tests::next_signer;
+    use crate::revocation::Revocation;
     use rsa::pkcs1::EncodeRsaPublicKey;
-    use rsa::pkcs8::{EncodePublicKey, LineEnding};
+    use rsa::pkcs8::LineEnding;
     use rsa::BigUint;
 
     const APP_UUID: &str = "101c139a-236c-11ef-b5e3-125eb8485a60";
@@ -135,6 +506,136 @@ mod tests {
         assert_eq!(verifier.public_key, public_key);
     }
 
+    #[test]
+    fn new_rejects_keys_below_the_key_policy() {
+        let public_key =
+            RsaPublicKey::new(BigUint::from_bytes_be(&[0xff; 64]), 65_537_u32.into()).unwrap();
+        let public_key_pem = public_key.to_public_key_pem(LineEnding::LF).unwrap();
+
+        assert!(matches!(
+            Verifier::new(APP_UUID, public_key_pem.clone()),
+            Err(Error::KeyPolicyViolation(_))
+        ));
+
+        let verifier =
+            Verifier::new_with_policy(APP_UUID, public_key_pem, &KeyPolicy::legacy()).unwrap();
+        assert_eq!(verifier.public_key, public_key);
+    }
+
+    #[test]
+    fn verification_errors_include_key_fingerprint() {
+        let verifier = crate::keys::tests::signer().verifier();
+
+        for version in [1, 2] {
+            let result =
+                verifier.verify_signature(version, "GET", "/", "", b"", "1669858655", "AAAA");
+
+            match result {
+                Err(e @ Error::SignatureVerifyError { fingerprint, .. }) => {
+                    assert_eq!(fingerprint, verifier.fingerprint());
+                    assert!(e.to_string().contains(&fingerprint.to_string()));
+                }
+                other => panic!("unexpected result: {other:?}"),
+            }
+        }
+        assert!(format!("{verifier:?}").contains(&verifier.fingerprint().to_string()));
+    }
+
+    #[test]
+    fn rotating_verifier_reports_the_matching_key() {
+        let old_verifier = next_signer().verifier();
+        let signer = crate::keys::tests::signer();
+        let mut verifier = RotatingVerifier::new(APP_UUID);
+        verifier
+            .add_key(VerificationKey::new(old_verifier.clone()))
+            .unwrap();
+        verifier
+            .add_key(VerificationKey::new(signer.verifier()))
+            .unwrap();
+
+        let signature = signer
+            .sign_string(2, "GET", "/", "", b"", "1669858655")
+            .unwrap();
+        let result = verifier.verify_signature(2, "GET", "/", "", b"", "1669858655", &signature);
+        assert_eq!(result.unwrap(), signer.fingerprint());
+
+        let result = verifier.verify_signature(2, "GET", "/", "", b"x", "1669858655", &signature);
+        assert!(matches!(
+            result,
+            Err(Error::NoMatchingKey { fingerprints, .. })
+                if fingerprints == [old_verifier.fingerprint(), signer.fingerprint()]
+        ));
+    }
+
+    #[test]
+    fn revoked_keys_are_rejected_after_verification() {
+        let signer = crate::keys::tests::signer();
+        let revocations = Arc::new(RevocationList::new());
+        let verifier = signer.verifier().with_revocation_list(revocations.clone());
+        let signature = signer
+            .sign_string(2, "GET", "/", "", b"", "1669858655")
+            .unwrap();
+        assert!(verifier
+            .verify_signature(2, "GET", "/", "", b"", "1669858655", &signature)
+            .is_ok());
+
+        revocations.revoke(Revocation::key(signer.fingerprint()));
+
+        assert!(matches!(
+            verifier.verify_signature(2, "GET", "/", "", b"", "1669858655", &signature),
+            Err(Error::KeyRevoked { .. })
+        ));
+        assert!(matches!(
+            verifier.verify_signature(2, "GET", "/", "", b"x", "1669858655", &signature),
+            Err(Error::SignatureVerifyError { .. })
+        ));
+    }
+
+    #[test]
+    fn rotating_verifier_skips_keys_outside_their_validity_period() {
+        let signer = crate::keys::tests::signer();
+        let now = SystemTime::now();
+        let hour = std::time::Duration::from_secs(3600);
+        let signature = signer
+            .sign_string(2, "GET", "/", "", b"", "1669858655")
+            .unwrap();
+
+        for key in [
+            VerificationKey::new(signer.verifier()).with_not_before(now + hour),
+            VerificationKey::new(signer.verifier()).with_not_after(now - hour),
+        ] {
+            let mut verifier = RotatingVerifier::new(APP_UUID);
+            verifier.add_key(key).unwrap();
+
+            assert!(matches!(
+                verifier.verify_signature(2, "GET", "/", "", b"", "1669858655", &signature),
+                Err(Error::NoValidKey(_))
+            ));
+        }
+
+        let mut verifier = RotatingVerifier::new(APP_UUID);
+        verifier
+            .add_key(
+                VerificationKey::new(signer.verifier())
+                    .with_not_before(now - hour)
+                    .with_not_after(now + hour),
+            )
+            .unwrap();
+        assert!(verifier
+            .verify_signature(2, "GET", "/", "", b"", "1669858655", &signature)
+            .is_ok());
+    }
+
+    #[test]
+    fn rotating_verifier_rejects_keys_for_other_apps() {
+        let mut verifier = RotatingVerifier::new("another-app");
+
+        assert!(matches!(
+            verifier.add_key(VerificationKey::new(next_signer().verifier())),
+            Err(Error::AppUuidMismatch { .. })
+        ));
+    }
+
     #[test]
     fn new_rejects_malformed_public_key_pem_as_a_public_key_error() {
         let malformed_public_keys = [
@@ -0,0 +1,170 @@
+use crate::error::Error;
+use crate::http::{
+    rejection_status, signature_headers, AuthenticatedApp, RequestAuthenticator, SigningVersions,
+};
+use crate::signer::Signer;
+use tungstenite::handshake::{client, server};
+
+/// Sign a tungstenite WebSocket handshake request, as made by `tungstenite::client` or
+/// `tokio_tungstenite::connect_async`, in the same way as any other `GET` request with an empty
+/// body. The path and query of the request URI are signed, and any MAuth headers already on the
+/// request are replaced.
+///
+/// ```
+/// # use mauth_core::http::{SigningVersions, MCC_AUTHENTICATION};
+/// # use mauth_core::keys::{KeyPair, KeySize, PrivateKeyFormat};
+/// # use mauth_core::signer::Signer;
+/// # use mauth_core::websocket::sign_handshake;
+/// # use tungstenite::client::IntoClientRequest;
+/// # let signer = Signer::new("101c139a-236c-11ef-b5e3-125eb8485a60", KeyPair::generate(KeySize::Rsa2048).unwrap().private_key_pem(PrivateKeyFormat::Pkcs1).unwrap().to_string()).unwrap();
+/// let mut request = "wss://realtime.example.com/updates?room=1".into_client_request().unwrap();
+/// sign_handshake(&signer, SigningVersions::V2, &mut request).unwrap();
+///
+/// assert!(request.headers().contains_key(MCC_AUTHENTICATION));
+/// ```
+pub fn sign_handshake(
+    signer: &Signer,
+    versions: SigningVersions,
+    request: &mut client::Request,
+) -> Result<(), Error> {
+    let uri = request.uri();
+    let headers = signature_headers(
+        signer,
+        versions,
+        request.method().as_str(),
+        uri.path(),
+        uri.query().unwrap_or_default(),
+        b"",
+    )?;
+
+    for (name, value) in headers {
+        request.headers_mut().insert(name, value);
+    }
+
+    Ok(())
+}
+
+/// Authenticate a WebSocket handshake request from within the callback of
+/// `tungstenite::accept_hdr` or `tokio_tungstenite::accept_hdr_async`, in the same way as
+/// `http::RequestAuthenticator::authenticate`. Failures can be turned into the response that
+/// rejects the upgrade with `websocket::handshake_rejection`.
+///
+/// The callback is synchronous, so with an async server this verifies the signature on the task
+/// accepting the connection.
+///
+/// ```no_run
+/// # use mauth_core::authenticator::Authenticator;
+/// # use mauth_core::http::RequestAuthenticator;
+/// # use mauth_core::verifier::RotatingVerifier;
+/// # use mauth_core::websocket::{authenticate_handshake, handshake_rejection};
+/// # use std::collections::HashMap;
+/// # use std::net::TcpListener;
+/// # use std::sync::Arc;
+/// # use tungstenite::handshake::server::{Request, Response};
+/// # let verifiers: HashMap<String, Arc<RotatingVerifier>> = HashMap::new();
+/// # let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+/// let authenticator = RequestAuthenticator::new(Authenticator::new(verifiers));
+/// let (stream, _) = listener.accept().unwrap();
+///
+/// let mut app = None;
+/// let websocket = tungstenite::accept_hdr(stream, |request: &Request, response: Response| {
+///     let authenticated = authenticate_handshake(&authenticator, request);
+///     app = Some(authenticated.map_err(|e| handshake_rejection(&e))?);
+///     Ok(response)
+/// });
+/// ```
+pub fn authenticate_handshake(
+    authenticator: &RequestAuthenticator,
+    request: &server::Request,
+) -> Result<AuthenticatedApp, Error> {
+    let uri = request.uri();
+    authenticator.authenticate(
+        request.method().as_str(),
+        uri.path(),
+        uri.query().unwrap_or_default(),
+        b"",
+        |name| request.headers().get(name)?.to_str().ok(),
+    )
+}
+
+/// The response that rejects a WebSocket upgrade which could not be authenticated, with the
+/// status from `http::rejection_status` and no body
+pub fn handshake_rejection(error: &Error) -> server::ErrorResponse {
+    let mut response = server::ErrorResponse::new(None);
+    *response.status_mut() = rejection_status(error);
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::tests::authenticator;
+    use crate::keys::tests::{signer, APP_UUID};
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+    use tungstenite::client::IntoClientRequest;
+    use tungstenite::handshake::server::Response;
+
+    /// Accept one connection on a local port, returning the app its handshake was authenticated
+    /// as, along with the URL to connect to
+    // The error type of the handshake callback is defined by tungstenite
+    #[allow(clippy::result_large_err)]
+    fn accept_one() -> (String, thread::JoinHandle<Result<AuthenticatedApp, Error>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://{}/updates?room=1", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let authenticator = RequestAuthenticator::new(authenticator());
+            let (stream, _) = listener.accept().unwrap();
+            let mut result = Err(Error::MissingAuthentication);
+            let _ = tungstenite::accept_hdr(stream, |request: &server::Request, response| {
+                result = authenticate_handshake(&authenticator, request);
+                match &result {
+                    Ok(_) => Ok::<Response, _>(response),
+                    Err(e) => Err(handshake_rejection(e)),
+                }
+            });
+            result
+        });
+
+        (url, server)
+    }
+
+    fn connect(request: client::Request) -> Result<(), tungstenite::Error> {
+        let authority = request.uri().authority().unwrap().to_string();
+        let stream = TcpStream::connect(authority).unwrap();
+        tungstenite::client(request, stream)
+            .map(|_| ())
+            .map_err(|e| match e {
+                tungstenite::HandshakeError::Failure(e) => e,
+                tungstenite::HandshakeError::Interrupted(_) => unreachable!(),
+            })
+    }
+
+    #[test]
+    fn signed_handshakes_are_accepted() {
+        let (url, server) = accept_one();
+        let mut request = url.into_client_request().unwrap();
+        sign_handshake(&signer(), SigningVersions::Both, &mut request).unwrap();
+
+        connect(request).unwrap();
+
+        let app = server.join().unwrap().unwrap();
+        assert_eq!((app.app_uuid.as_str(), app.version), (APP_UUID, 2));
+    }
+
+    #[test]
+    fn unsigned_handshakes_are_rejected() {
+        let (url, server) = accept_one();
+
+        let result = connect(url.into_client_request().unwrap());
+
+        assert!(matches!(
+            result,
+            Err(tungstenite::Error::Http(response)) if response.status() == 401
+        ));
+        assert!(matches!(
+            server.join().unwrap(),
+            Err(Error::MissingAuthentication)
+        ));
+    }
+}
@@ -0,0 +1,56 @@
+use mauth_core::keys::{KeyPair, KeySize, PrivateKeyFormat, PublicKeyFormat};
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+const APP_UUID: &str = "101c139a-236c-11ef-b5e3-125eb8485a60";
+
+fn mauth_core(args: &[&str], directory: &Path) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_mauth-core"))
+        .args(args)
+        .current_dir(directory)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn signed_files_verify_until_they_change() {
+    let directory = tempfile::tempdir().unwrap();
+    let key_pair = KeyPair::generate(KeySize::Rsa2048).unwrap();
+    let private_key = key_pair.private_key_pem(PrivateKeyFormat::Pkcs1).unwrap();
+    fs::write(directory.path().join("private.pem"), private_key.as_bytes()).unwrap();
+    let public_key = key_pair.public_key_pem(PublicKeyFormat::Spki).unwrap();
+    fs::write(directory.path().join("public.pem"), public_key).unwrap();
+    fs::write(directory.path().join("export.csv"), "id,name\n1,a\n").unwrap();
+
+    let signed = mauth_core(
+        &[
+            "sign",
+            "--app-uuid",
+            APP_UUID,
+            "--private-key",
+            "private.pem",
+            "export.csv",
+        ],
+        directory.path(),
+    );
+    assert!(signed.status.success());
+    assert!(directory.path().join("export.csv.mauthsig").exists());
+
+    let verify = [
+        "verify",
+        "--app-uuid",
+        APP_UUID,
+        "--public-key",
+        "public.pem",
+        "export.csv",
+    ];
+    let verified = mauth_core(&verify, directory.path());
+    assert!(verified.status.success());
+    assert!(String::from_utf8_lossy(&verified.stdout).contains(APP_UUID));
+
+    fs::write(directory.path().join("export.csv"), "id,name\n1,b\n").unwrap();
+    let tampered = mauth_core(&verify, directory.path());
+    assert!(!tampered.status.success());
+    assert!(String::from_utf8_lossy(&tampered.stderr).contains("digest"));
+}